@@ -0,0 +1,178 @@
+use std::cmp;
+use std::fmt;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use client::Transport;
+use error::Result;
+use issue::Issue;
+use jira::Jira;
+
+/// How many issues `ob bulk` works on at the same time, unless told otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// A change `ob bulk` makes to every issue.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    Assign(String),
+    AddLabel(String),
+    Comment(String),
+    /// Issues that already have the status are left alone.
+    Transition(String),
+}
+
+impl Operation {
+    fn apply<T: Transport>(&self, jira: &Jira<T>, issue: &Issue) -> Result<()> {
+        match *self {
+            Operation::Assign(ref username) => jira.assign(&issue.key, username),
+            Operation::AddLabel(ref label) => {
+                jira.update_labels(&issue.key, &[label.clone()], &[])
+            }
+            Operation::Comment(ref body) => jira.add_comment(&issue.key, body),
+            Operation::Transition(ref status) => {
+                if issue.status.eq_ignore_ascii_case(status) {
+                    return Ok(());
+                }
+                jira.transition_to(&issue.key, &[status.as_str()])
+                    .map(|_| ())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Assign(ref username) => write!(f, "assign to {}", username),
+            Operation::AddLabel(ref label) => write!(f, "add the label {}", label),
+            Operation::Comment(_) => write!(f, "comment"),
+            Operation::Transition(ref status) => write!(f, "move to {}", status),
+        }
+    }
+}
+
+/// What happened to one issue, the error names the operation that failed.
+pub type Outcome = ::std::result::Result<(), String>;
+
+/// Applies the operations in order to every issue, working on at most
+/// `concurrency` issues at a time. The rest of an issue's operations are
+/// skipped once one of them fails. Outcomes are in the same order as `issues`.
+pub fn run<T>(
+    jira: &Jira<T>,
+    issues: &[Issue],
+    operations: &[Operation],
+    concurrency: usize,
+) -> Vec<Outcome>
+where
+    T: Transport + Clone + Send + 'static,
+{
+    let queue: Vec<(usize, Issue)> = issues.iter().cloned().enumerate().collect();
+    let queue = Arc::new(Mutex::new(queue.into_iter()));
+    let operations = Arc::new(operations.to_vec());
+    let (sender, receiver) = mpsc::channel();
+
+    let workers = cmp::max(1, cmp::min(concurrency, issues.len()));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let jira = jira.clone();
+            let queue = queue.clone();
+            let operations = operations.clone();
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let (index, issue) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let mut outcome = Ok(());
+                for operation in operations.iter() {
+                    if let Err(why) = operation.apply(&jira, &issue) {
+                        outcome = Err(format!("{}: {}", operation, why));
+                        break;
+                    }
+                }
+                if sender.send((index, outcome)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    // the workers hold the only senders left, so this ends when they do
+    drop(sender);
+
+    let mut outcomes: Vec<Option<Outcome>> = vec![None; issues.len()];
+    for (index, outcome) in receiver {
+        outcomes[index] = Some(outcome);
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+    outcomes
+        .into_iter()
+        .map(|outcome| outcome.unwrap_or_else(|| Err("never got to it".to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{Method, MockTransport};
+
+    const AUTH: &str = "Ym9iOmh1bnRlcjI="; // bob:hunter2
+
+    #[test]
+    fn every_issue_gets_an_outcome_in_order() {
+        let transport = MockTransport::new()
+            .on(Method::Put, "/rest/api/2/issue/FOO-1/assignee", 204, "")
+            .on(Method::Put, "/rest/api/2/issue/FOO-3/assignee", 204, "")
+            .on(Method::Post, "/rest/api/2/issue/FOO-1/comment", 201, "{}")
+            .on(Method::Post, "/rest/api/2/issue/FOO-3/comment", 201, "{}");
+        let jira = Jira::with_transport(transport.clone(), AUTH, "https://jira.example.com/")
+            .unwrap();
+        let issues = vec![
+            Issue::example("FOO-1"),
+            Issue::example("FOO-2"),
+            Issue::example("FOO-3"),
+        ];
+        let operations = vec![
+            Operation::Assign("bob".to_string()),
+            Operation::Comment("cleaning up".to_string()),
+        ];
+
+        let outcomes = run(&jira, &issues, &operations, 2);
+        assert_eq!(
+            outcomes,
+            vec![
+                Ok(()),
+                Err("assign to bob: issue FOO-2 not found".to_string()),
+                Ok(()),
+            ]
+        );
+        // FOO-2 isn't commented on once assigning it failed
+        let comments = transport
+            .requests()
+            .iter()
+            .filter(|request| request.url.path().ends_with("/comment"))
+            .count();
+        assert_eq!(comments, 2);
+    }
+
+    #[test]
+    fn issues_already_in_the_status_are_not_moved() {
+        let transport = MockTransport::new();
+        let jira = Jira::with_transport(transport.clone(), AUTH, "https://jira.example.com/")
+            .unwrap();
+        let outcomes = run(
+            &jira,
+            &[Issue {
+                status: "Done".to_string(),
+                ..Issue::example("FOO-1")
+            }],
+            &[Operation::Transition("done".to_string())],
+            DEFAULT_CONCURRENCY,
+        );
+        assert_eq!(outcomes, vec![Ok(())]);
+        // just the login
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use client::Transport;
+use error::Result;
+use issue::{Issue, IssueVec};
+use jira::Jira;
+use jql;
+
+/// Results older than this are fetched again in full instead of patched up
+/// with an incremental query, which also takes care of deleted issues.
+const FULL_SYNC_AFTER_HOURS: i64 = 24;
+
+/// Extra minutes added to incremental queries to cover clock skew between us
+/// and jira.
+const SYNC_MARGIN_MINUTES: i64 = 2;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CachedQuery {
+    pub query: String,
+    pub synced_at: DateTime<Utc>,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CachedIssue {
+    pub fetched_at: DateTime<Utc>,
+    pub issue: Issue,
+}
+
+/// An on-disk cache of query results and issues, kept as json files under
+/// `~/.cache/ob/<instance>` so that `--offline` has something to show.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+// file names have to stay stable between builds, so no std hasher here
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Cache {
+        Cache { dir: dir }
+    }
+
+    /// The cache for one jira instance under `dir`, since profiles can point
+    /// at different instances that use the same issue keys.
+    pub fn for_instance(dir: PathBuf, jira_url: &str) -> Cache {
+        let instance = jira_url.trim().trim_right_matches('/');
+        Cache::new(dir.join(format!("{:016x}", fnv1a(instance))))
+    }
+
+    /// `$XDG_CACHE_HOME/ob`, falling back to `~/.cache/ob`.
+    pub fn default_dir() -> Option<PathBuf> {
+        match env::var_os("XDG_CACHE_HOME") {
+            Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("ob")),
+            _ => env::home_dir().map(|home| home.join(".cache").join("ob")),
+        }
+    }
+
+    fn query_path(&self, query: &str) -> PathBuf {
+        self.dir
+            .join("queries")
+            .join(format!("{:016x}.json", fnv1a(query.trim())))
+    }
+
+    fn issue_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join("issues")
+            .join(format!("{}.json", key.to_uppercase()))
+    }
+
+    // a missing or unreadable entry is just a cache miss
+    fn read<T: DeserializeOwned>(&self, path: PathBuf) -> Option<T> {
+        let file = File::open(&path).ok()?;
+        match serde_json::from_reader(file) {
+            Ok(value) => Some(value),
+            Err(why) => {
+                debug!("ignoring cache entry {}: {}", path.display(), why);
+                None
+            }
+        }
+    }
+
+    // writes to a temporary file first so a crash never leaves half an entry
+    fn write<T: Serialize>(&self, path: PathBuf, value: &T) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(serde_json::to_string(value)?.as_bytes())?;
+        }
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    pub fn query(&self, query: &str) -> Option<CachedQuery> {
+        self.read::<CachedQuery>(self.query_path(query))
+            .filter(|cached| cached.query == query.trim())
+    }
+
+    pub fn issue(&self, key: &str) -> Option<CachedIssue> {
+        self.read(self.issue_path(key))
+    }
+
+    pub fn store_issue(&self, issue: &Issue) -> Result<()> {
+        let cached = CachedIssue {
+            fetched_at: Utc::now(),
+            issue: issue.clone(),
+        };
+        self.write(self.issue_path(&issue.key), &cached)
+    }
+
+    fn store_query(&self, query: &str, synced_at: DateTime<Utc>, issues: &[Issue]) -> Result<()> {
+        for issue in issues {
+            match self.issue(&issue.key) {
+                Some(cached) => self.store_issue(&with_search_fields(cached.issue, issue))?,
+                None => self.store_issue(issue)?,
+            }
+        }
+        self.write(
+            self.query_path(query),
+            &CachedQuery {
+                query: query.to_string(),
+                synced_at: synced_at,
+                issues: issues.to_vec(),
+            },
+        )
+    }
+
+    fn forget_query(&self, query: &str) {
+        let path = self.query_path(query);
+        if let Err(why) = fs::remove_file(&path) {
+            debug!("could not remove cache entry {}: {}", path.display(), why);
+        }
+    }
+
+    /// Fetches a single issue from jira and caches it.
+    pub fn fetch_issue<T: Transport>(&self, jira: &Jira<T>, key: &str) -> Result<Issue> {
+        let issue = jira.issue(key)?;
+        // the cache is a convenience, not being able to write it shouldn't
+        // stop anyone from working
+        if let Err(why) = self.store_issue(&issue) {
+            warn!("could not update the cache: {}", why);
+        }
+        Ok(issue)
+    }
+
+    /// Runs a query against jira and caches the result. When a recent enough
+    /// result is already cached (and `refresh` isn't set) only the issues
+    /// updated since then are fetched and merged into it.
+    pub fn sync_query<T: Transport>(
+        &self,
+        jira: &Jira<T>,
+        query: &str,
+        refresh: bool,
+    ) -> Result<IssueVec> {
+        let query = query.trim();
+        let now = Utc::now();
+        let cached = if refresh { None } else { self.query(query) };
+
+        let issues = match cached {
+            Some(cached) if incremental(query, &cached, now) => {
+                let minutes = (now - cached.synced_at).num_minutes() + SYNC_MARGIN_MINUTES;
+                debug!("refreshing cached results for {} from the last {}m", query, minutes);
+                match merge_updates(jira, query, cached.issues, minutes) {
+                    Ok(issues) => issues,
+                    // jira rejects the whole query once one of the cached
+                    // keys is deleted or moved, so start over
+                    Err(why) => {
+                        debug!("could not refresh {}, fetching it in full: {}", query, why);
+                        self.forget_query(query);
+                        jira.query(query)?.into_vec()
+                    }
+                }
+            }
+            _ => jira.query(query)?.into_vec(),
+        };
+
+        if let Err(why) = self.store_query(query, now, &issues) {
+            warn!("could not update the cache: {}", why);
+        }
+        Ok(IssueVec::from(issues))
+    }
+}
+
+// searches only return the fields JQLQuery asks for, so they update the cached
+// copy of an issue rather than replace what `ob issue` fetched in full
+fn with_search_fields(cached: Issue, found: &Issue) -> Issue {
+    Issue {
+        summary: found.summary.clone(),
+        status: found.status.clone(),
+        assignee: found.assignee.clone(),
+        reporter: found.reporter.clone(),
+        labels: found.labels.clone(),
+        ..cached
+    }
+}
+
+// wrapping the query in parens breaks an ORDER BY clause, so those are always
+// fetched in full
+fn incremental(query: &str, cached: &CachedQuery, now: DateTime<Utc>) -> bool {
+    !query.to_lowercase().contains("order by")
+        && now - cached.synced_at < Duration::hours(FULL_SYNC_AFTER_HOURS)
+        && now >= cached.synced_at
+}
+
+// fetches what changed in the last `minutes`: issues that (still) match go
+// in, cached issues that changed and no longer match come out
+fn merge_updates<T: Transport>(
+    jira: &Jira<T>,
+    query: &str,
+    cached: Vec<Issue>,
+    minutes: i64,
+) -> Result<Vec<Issue>> {
+    let since = format!("updated >= {}", jql::quote(&format!("-{}m", minutes)));
+    let changed = jira.query(&format!("({}) AND {}", query, since))?;
+
+    let keys: Vec<&str> = cached.iter().map(|issue| issue.key.as_str()).collect();
+    let gone: Vec<String> = if keys.is_empty() {
+        Vec::new()
+    } else {
+        jira.query(&format!(
+            "key in {} AND {} AND NOT ({})",
+            jql::List::strings(&keys),
+            since,
+            query
+        ))?
+            .iter()
+            .map(|issue| issue.key.clone())
+            .collect()
+    };
+
+    let mut issues: Vec<Issue> = cached
+        .into_iter()
+        .filter(|issue| !gone.contains(&issue.key))
+        .collect();
+    for issue in changed.into_vec() {
+        match issues.iter().position(|cached| cached.key == issue.key) {
+            Some(idx) => issues[idx] = issue,
+            None => issues.push(issue),
+        }
+    }
+    Ok(issues)
+}
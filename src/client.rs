@@ -0,0 +1,543 @@
+use log::Level;
+use reqwest;
+use serde_json;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use error::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// A single HTTP request, independent of the library used to send it.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: Url) -> Request {
+        Request {
+            method: method,
+            url: url,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Request {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn json(self, body: String) -> Request {
+        let mut request = self.header("Content-Type", "application/json; charset=utf-8");
+        request.body = Some(body.into_bytes());
+        request
+    }
+
+    /// A `multipart/form-data` body with one part named `field` for each
+    /// (file name, contents) pair, the way browsers upload files.
+    pub fn multipart(self, field: &str, files: &[(String, Vec<u8>)]) -> Request {
+        let boundary = boundary_for(files, random_boundary);
+
+        let mut body = Vec::new();
+        for &(ref name, ref contents) in files {
+            // quotes would end the file name early
+            let name = name.replace('"', "_");
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                     Content-Type: application/octet-stream\r\n\r\n",
+                    boundary, field, name
+                ).as_bytes(),
+            );
+            body.extend_from_slice(contents);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        let mut request = self.header(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={}", boundary),
+        );
+        request.body = Some(body);
+        request
+    }
+
+    /// The body as text, for looking at what was sent.
+    pub fn text(&self) -> String {
+        self.body
+            .as_ref()
+            .map_or(String::new(), |body| String::from_utf8_lossy(body).into_owned())
+    }
+}
+
+// the first boundary that appears nowhere in the files, a boundary in a file
+// would end its part early
+fn boundary_for<F>(files: &[(String, Vec<u8>)], mut next: F) -> String
+    where F: FnMut() -> String
+{
+    loop {
+        let boundary = next();
+        let appears = |bytes: &[u8]| {
+            bytes.windows(boundary.len()).any(|window| window == boundary.as_bytes())
+        };
+        if !files.iter().any(|&(ref name, ref contents)| appears(name.as_bytes()) || appears(contents)) {
+            return boundary;
+        }
+    }
+}
+
+// RandomState is seeded from the OS, so hashing with two of them gives 128
+// random bits without needing another crate
+fn random_boundary() -> String {
+    let mut boundary = String::from("------------------------ob");
+    for half in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(half);
+        boundary.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    boundary
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Sends requests on behalf of `Jira`. This is the seam that lets the client
+/// logic run against something other than a live server.
+pub trait Transport {
+    fn send(&self, request: Request) -> Result<Response>;
+
+    /// Sends the request, logging a summary line at debug level and the full
+    /// (redacted) request and response at trace level.
+    fn send_logged(&self, request: Request) -> Result<Response> {
+        let summary = format!("{} {}", request.method.as_str(), request.url);
+        if log_enabled!(Level::Trace) {
+            trace!(
+                "request {}\n{}",
+                summary,
+                describe(&request.headers, request.body.as_ref().map(|body| body.as_slice()))
+            );
+        }
+
+        let started = Instant::now();
+        let result = self.send(request);
+        let elapsed = started.elapsed();
+        let millis = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos()) / 1_000_000;
+
+        match result {
+            Ok(ref res) => {
+                debug!("{} -> {} ({}ms)", summary, res.status, millis);
+                if log_enabled!(Level::Trace) {
+                    trace!(
+                        "response {} {}\n{}",
+                        summary,
+                        res.status,
+                        describe(&res.headers, Some(&res.body))
+                    );
+                }
+            }
+            Err(ref why) => debug!("{} failed after {}ms: {}", summary, millis, why),
+        }
+        result
+    }
+}
+
+// headers and body fields that carry credentials or session ids, these never
+// make it into a log
+const SECRET_HEADERS: &[&str] = &["authorization", "cookie", "set-cookie", "proxy-authorization"];
+const SECRET_FIELDS: &[&str] = &["password", "session"];
+const REDACTED: &str = "<redacted>";
+
+fn redact_json(value: &mut Value) {
+    match *value {
+        Value::Object(ref mut map) => for (key, field) in map.iter_mut() {
+            if SECRET_FIELDS.contains(&key.to_lowercase().as_str()) {
+                *field = Value::String(REDACTED.to_string());
+            } else {
+                redact_json(field);
+            }
+        },
+        Value::Array(ref mut items) => for item in items.iter_mut() {
+            redact_json(item);
+        },
+        _ => {}
+    }
+}
+
+// renders headers and body for the trace log with anything secret replaced
+fn describe(headers: &[(String, String)], body: Option<&[u8]>) -> String {
+    let mut lines: Vec<String> = headers
+        .iter()
+        .map(|&(ref name, ref value)| {
+            if SECRET_HEADERS.contains(&name.to_lowercase().as_str()) {
+                format!("{}: {}", name, REDACTED)
+            } else {
+                format!("{}: {}", name, value)
+            }
+        })
+        .collect();
+
+    if let Some(body) = body {
+        // uploaded files are of no use in a log, and may well be binary
+        let uploads = headers.iter().any(|&(ref name, ref value)| {
+            name.eq_ignore_ascii_case("content-type") && value.starts_with("multipart/")
+        });
+        lines.push(String::new());
+        if uploads {
+            lines.push(format!("<{} bytes of file uploads>", body.len()));
+            return lines.join("\n");
+        }
+        lines.push(match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                json.to_string()
+            }
+            Err(_) => String::from_utf8_lossy(body).into_owned(),
+        });
+    }
+    lines.join("\n")
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_read_timeout() -> u64 {
+    30
+}
+
+/// Connection settings for talking to Jira, read from the `http` section of
+/// the config file.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HttpOptions {
+    /// Proxy url used for all requests, e.g. `http://proxy.corp:3128`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// PEM file with extra CA certificates to trust, for internal CAs
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
+    /// Skip TLS certificate verification entirely. Never use this outside of
+    /// debugging a broken setup.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+    /// Seconds to wait for a connection to be established
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// Seconds to wait for a whole request to complete
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: u64,
+    /// PKCS#12 archive holding a client certificate and its key
+    #[serde(default)]
+    pub client_certificate: Option<String>,
+    #[serde(default)]
+    pub client_certificate_password: Option<String>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> HttpOptions {
+        HttpOptions {
+            proxy: None,
+            ca_bundle: None,
+            insecure_skip_verify: false,
+            connect_timeout: default_connect_timeout(),
+            read_timeout: default_read_timeout(),
+            client_certificate: None,
+            client_certificate_password: None,
+        }
+    }
+}
+
+// expands a leading `~/` so paths in the config can be written the way they
+// would be typed into a shell
+fn expand_home(path: &str) -> PathBuf {
+    match (path.starts_with("~/"), env::home_dir()) {
+        (true, Some(home)) => home.join(&path[2..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    File::open(expand_home(path))?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+#[derive(Clone)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    pub fn new(options: &HttpOptions) -> Result<HttpTransport> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(options.connect_timeout))
+            .timeout(Duration::from_secs(options.read_timeout));
+
+        if let Some(ref proxy) = options.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+
+        if let Some(ref ca_bundle) = options.ca_bundle {
+            let pem = read_file(ca_bundle)?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+
+        if let Some(ref client_certificate) = options.client_certificate {
+            let der = read_file(client_certificate)?;
+            let password = match options.client_certificate_password {
+                Some(ref password) => password.as_str(),
+                None => "",
+            };
+            builder = builder.identity(reqwest::Identity::from_pkcs12_der(&der, password)?);
+        }
+
+        if options.insecure_skip_verify {
+            warn!(
+                "TLS certificate verification is disabled (http.insecure_skip_verify). \
+                 Anyone on the network path can read and alter your Jira traffic, \
+                 including your credentials."
+            );
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(HttpTransport {
+            client: builder.build()?,
+        })
+    }
+}
+
+impl Transport for HttpTransport {
+    fn send(&self, request: Request) -> Result<Response> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Put => reqwest::Method::PUT,
+            Method::Delete => reqwest::Method::DELETE,
+        };
+
+        let mut builder = self.client.request(method, request.url);
+        for (name, value) in request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let mut res = builder.send()?;
+        let mut body = Vec::new();
+        res.read_to_end(&mut body)?;
+
+        Ok(Response {
+            status: res.status().as_u16(),
+            headers: res.headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    value
+                        .to_str()
+                        .ok()
+                        .map(|value| (name.as_str().to_string(), value.to_string()))
+                })
+                .collect(),
+            body: body,
+        })
+    }
+}
+
+#[cfg(test)]
+pub use self::mock::MockTransport;
+
+#[cfg(test)]
+mod mock {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::{Method, Request, Response, Transport};
+    use error::Result;
+
+    /// An in-memory transport that serves canned responses by method and path
+    /// and remembers every request it was sent. Clones share their state, so
+    /// a test can keep one and hand the other to `Jira`.
+    ///
+    /// Registering a route more than once queues the responses: they are
+    /// served in order and the last one is repeated from then on.
+    #[derive(Clone, Default)]
+    pub struct MockTransport {
+        routes: Arc<Mutex<HashMap<(Method, String), Vec<Response>>>>,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl MockTransport {
+        /// A transport that already accepts the session login.
+        pub fn new() -> MockTransport {
+            MockTransport::default().on(
+                Method::Post,
+                "/rest/auth/1/session",
+                200,
+                include_str!("../tests/fixtures/session.json"),
+            )
+        }
+
+        pub fn on(self, method: Method, path: &str, status: u16, body: &str) -> MockTransport {
+            self.respond(
+                method,
+                path,
+                Response {
+                    status: status,
+                    headers: Vec::new(),
+                    body: body.as_bytes().to_vec(),
+                },
+            )
+        }
+
+        pub fn respond(self, method: Method, path: &str, response: Response) -> MockTransport {
+            self.routes
+                .lock()
+                .unwrap()
+                .entry((method, path.to_string()))
+                .or_insert_with(Vec::new)
+                .push(response);
+            self
+        }
+
+        pub fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn send(&self, request: Request) -> Result<Response> {
+            let key = (request.method, request.url.path().to_string());
+            self.requests.lock().unwrap().push(request);
+            Ok(match self.routes.lock().unwrap().get_mut(&key) {
+                Some(responses) => if responses.len() > 1 {
+                    responses.remove(0)
+                } else {
+                    responses[0].clone()
+                },
+                None => Response {
+                    status: 404,
+                    headers: Vec::new(),
+                    body: b"{\"errorMessages\":[\"no route\"],\"errors\":{}}".to_vec(),
+                },
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boundary_for, describe, random_boundary, Method, Request};
+    use url::Url;
+
+    #[test]
+    fn describe_redacts_credentials() {
+        let headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Cookie".to_string(), "JSESSIONID=abc".to_string()),
+        ];
+        let body = "{\"username\":\"bob\",\"password\":\"hunter2\"}";
+        let described = describe(&headers, Some(body.as_bytes()));
+
+        assert!(described.contains("Content-Type: application/json"));
+        assert!(described.contains("Cookie: <redacted>"));
+        assert!(described.contains("\"username\":\"bob\""));
+        assert!(!described.contains("hunter2"));
+        assert!(!described.contains("abc"));
+    }
+
+    #[test]
+    fn describe_redacts_session_ids() {
+        let body = include_str!("../tests/fixtures/session.json");
+        let described = describe(&[], Some(body.as_bytes()));
+        assert!(!described.contains("6E3487971234567896704A9EB4AE501F"));
+    }
+
+    #[test]
+    fn multipart_bodies_hold_every_file() {
+        let url = Url::parse("https://jira.example.com/").unwrap();
+        let files = vec![
+            ("build.log".to_string(), b"line 1\nline 2".to_vec()),
+            ("shot \"1\".png".to_string(), vec![0x89, 0x50, 0x4e, 0x47]),
+        ];
+        let request = Request::new(Method::Post, url).multipart("file", &files);
+
+        let content_type = &request.headers[0].1;
+        let boundary = content_type.split("boundary=").nth(1).unwrap();
+        let body = request.text();
+        assert!(content_type.starts_with("multipart/form-data"));
+        assert_eq!(body.matches(&format!("--{}\r\n", boundary)).count(), 2);
+        assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+        assert!(body.contains("name=\"file\"; filename=\"build.log\"\r\n"));
+        assert!(body.contains("filename=\"shot _1_.png\""));
+        assert!(body.contains("\r\n\r\nline 1\nline 2\r\n"));
+
+        let described = describe(&request.headers, request.body.as_ref().map(|b| b.as_slice()));
+        assert!(!described.contains("line 1"));
+    }
+
+    #[test]
+    fn multipart_boundaries_never_appear_in_the_files() {
+        let files = vec![("notes.txt".to_string(), b"--first--\r\n".to_vec())];
+        let mut candidates = vec!["second", "first"];
+        let boundary = boundary_for(&files, || candidates.pop().unwrap().to_string());
+        assert_eq!(boundary, "second");
+
+        let files = vec![("first.txt".to_string(), Vec::new())];
+        let mut candidates = vec!["second", "first"];
+        let boundary = boundary_for(&files, || candidates.pop().unwrap().to_string());
+        assert_eq!(boundary, "second");
+
+        let boundary = random_boundary();
+        assert!(boundary.len() >= 50);
+        assert!(boundary != random_boundary());
+    }
+}
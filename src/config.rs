@@ -1,28 +1,139 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io;
 use std::io::Read;
 use std::io::Write;
+use std::process::Command;
 use std::str;
 
 use base64::encode;
 use rpassword;
-use yaml_rust::YamlLoader;
+use serde_path_to_error;
+use serde_yaml;
+use serde_yaml::{Mapping, Value};
 
+use client::HttpOptions;
 use error::{ErrorKind, Result};
+use jira::{Jira, RetryPolicy, DEFAULT_EPIC_LINK_FIELD};
+use jql;
 
-// handle invalid configs by raising InvalidConfig if ever we try to get a value
-// and it's not there
-fn extract<F, T>(extractor: F) -> Result<T>
-where
-    F: Fn() -> Option<T>,
-{
-    match extractor() {
-        Some(val) => Ok(val),
-        None => Err(ErrorKind::InvalidConfig.into()),
+/// The config format version written by this build. Older files are migrated
+/// forward when they are loaded.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// System wide settings, overridden by everything else.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/ob.yml";
+
+/// The name of the per-repository config file, looked for at the root of the
+/// git repository containing the current directory.
+pub const REPO_CONFIG_NAME: &str = ".ob.yml";
+
+// each entry upgrades a config from version (index + 1) to (index + 2)
+const MIGRATIONS: &[fn(&mut Mapping)] = &[migrate_v1_to_v2];
+
+// version 2 introduced named profiles
+fn migrate_v1_to_v2(root: &mut Mapping) {
+    let key = Value::String("profiles".to_string());
+    if !root.contains_key(&key) {
+        root.insert(key, Value::Mapping(Mapping::new()));
     }
 }
 
+#[cfg(target_os = "macos")]
+const DEFAULT_BROWSER_COMMAND: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_BROWSER_COMMAND: &str = "xdg-open";
+
+/// The branch `ob branch` creates, see `git::branch_name` for placeholders.
+pub const DEFAULT_BRANCH_TEMPLATE: &str = "{key}-{summary-slug}";
+
+// the bottom layer, everything that isn't required has a value here
+fn builtin_defaults() -> Value {
+    let mut section = Mapping::new();
+    section.insert(
+        Value::String("npc_users".to_string()),
+        Value::Sequence(vec![Value::String("Unassigned".to_string())]),
+    );
+    section.insert(
+        Value::String("open_in_browser".to_string()),
+        Value::Bool(false),
+    );
+    section.insert(
+        Value::String("browser_command".to_string()),
+        Value::String(DEFAULT_BROWSER_COMMAND.to_string()),
+    );
+    section.insert(
+        Value::String("branch_template".to_string()),
+        Value::String(DEFAULT_BRANCH_TEMPLATE.to_string()),
+    );
+    section.insert(
+        Value::String("epic_link_field".to_string()),
+        Value::String(DEFAULT_EPIC_LINK_FIELD.to_string()),
+    );
+
+    let mut root = Mapping::new();
+    root.insert(
+        Value::String("config".to_string()),
+        Value::Mapping(section),
+    );
+    Value::Mapping(root)
+}
+
+// environment variables that override a single setting, values for list
+// settings are comma separated
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("OB_JIRA_URL", "jira"),
+    ("OB_USERNAME", "username"),
+    ("OB_AUTH", "auth"),
+    ("OB_PROJECT_KEYS", "project_keys"),
+    ("OB_NPC_USERS", "npc_users"),
+    ("OB_OPEN_IN_BROWSER", "open_in_browser"),
+    ("OB_BROWSER_COMMAND", "browser_command"),
+    ("OB_BRANCH_TEMPLATE", "branch_template"),
+    ("OB_DEFAULT_PROJECT_KEY", "new_issue_defaults.project_key"),
+    ("OB_DEFAULT_ASSIGNEE", "new_issue_defaults.assignee"),
+    ("OB_DEFAULT_LABELS", "new_issue_defaults.labels"),
+    ("OB_HTTP_PROXY", "http.proxy"),
+    ("OB_CA_BUNDLE", "http.ca_bundle"),
+];
+
+const LIST_SETTINGS: &[&str] = &["project_keys", "npc_users", "new_issue_defaults.labels"];
+
+const NUMERIC_SETTINGS: &[&str] = &[
+    "http.connect_timeout",
+    "http.read_timeout",
+    "retry.max_retries",
+    "retry.base_delay_ms",
+    "retry.max_delay_ms",
+];
+
+#[derive(Deserialize)]
+struct Settings {
+    jira: String,
+    username: String,
+    auth: String,
+    project_keys: Vec<String>,
+    npc_users: Vec<String>,
+    open_in_browser: bool,
+    browser_command: String,
+    branch_template: String,
+    epic_link_field: String,
+    #[serde(default)] field_aliases: BTreeMap<String, String>,
+    #[serde(default)] new_issue_defaults: DefaultSettings,
+    #[serde(default)] http: HttpOptions,
+    #[serde(default)] retry: RetryPolicy,
+}
+
+#[derive(Deserialize, Default)]
+struct DefaultSettings {
+    project_key: Option<String>,
+    assignee: Option<String>,
+    #[serde(default)] labels: Vec<String>,
+}
+
 pub struct Defaults {
     pub project_key: String,
     pub assignee: String,
@@ -37,166 +148,936 @@ pub struct Config {
     pub npc_users: Vec<String>,
     pub open_in_browser: bool,
     pub browser_command: String,
+    pub branch_template: String,
+    pub epic_link_field: String,
+    /// Short names for fields, e.g. `team: customfield_10200`, used by `ob import`.
+    pub field_aliases: BTreeMap<String, String>,
     pub defaults: Defaults,
+    pub http: HttpOptions,
+    pub retry: RetryPolicy,
+}
+
+enum SetupAction {
+    Merge,
+    Edit,
+    Overwrite,
+    Abort,
 }
 
 impl Config {
-    pub fn new(path: &Path) -> Result<Config> {
-        let mut file = try!(File::open(&path));
-        let mut s = String::new();
-        try!(file.read_to_string(&mut s));
-        let docs = try!(YamlLoader::load_from_str(&s));
-        let data = &docs[0];
-
-        let jira_url = try!(extract(|| data["config"]["jira"].as_str())).to_string();
-        let auth_data = try!(extract(|| data["config"]["auth"].as_str())).to_string();
-        let username = try!(extract(|| data["config"]["username"].as_str())).to_string();
-
-        let raw_projects = try!(extract(|| data["config"]["project_keys"].as_vec()));
-        let mut projects = Vec::new();
-        for elem in raw_projects.iter() {
-            let val = try!(extract(|| elem.as_str())).to_string();
-            projects.push(val);
-        }
-
-        let raw_npc_users = try!(extract(|| data["config"]["npc_users"].as_vec()));
-        let mut npc_users = Vec::new();
-        for elem in raw_npc_users.iter() {
-            let val = try!(extract(|| elem.as_str())).to_string();
-            npc_users.push(val);
-        }
-        let open_in_browser = try!(extract(|| data["config"]["open_in_browser"].as_bool()));
-        let browser_command =
-            try!(extract(|| data["config"]["browser_command"].as_str())).to_string();
-
-        let default_project_key = try!(extract(|| data["config"]["new_issue_defaults"]
-            ["project_key"]
-            .as_str()))
-            .to_string();
-        let default_assignee = try!(extract(|| data["config"]["new_issue_defaults"]["assignee"]
-            .as_str()))
-            .to_string();
-        let raw_default_labels = try!(extract(|| data["config"]["new_issue_defaults"]["labels"]
-            .as_vec()));
-        let mut default_labels = Vec::new();
-        for elem in raw_default_labels {
-            let val = try!(extract(|| elem.as_str())).to_string();
-            default_labels.push(val);
+    /// Loads the config by layering, from lowest to highest precedence: the
+    /// built-in defaults, `/etc/ob.yml`, the user's config at `path`, the
+    /// selected profile, the `.ob.yml` at the root of the current git
+    /// repository, `OB_*` environment variables and finally `key=value`
+    /// overrides from the command line. The repository's file can only set
+    /// the settings in `REPO_SETTINGS`.
+    pub fn new(path: &Path, profile: Option<&str>, overrides: &[String]) -> Result<Config> {
+        // with a dotfiles repository in $HOME the user's own file is found as
+        // the repository's, and it's already been read
+        let repo_config =
+            repo_config_path().filter(|repo| repo.exists() && !same_file(repo, path));
+        let sources = Sources {
+            files: vec![PathBuf::from(SYSTEM_CONFIG_PATH), path.to_path_buf()],
+            repo_config: repo_config,
+            env: ENV_OVERRIDES
+                .iter()
+                .filter_map(|&(var, key)| env::var(var).ok().map(|raw| (key, raw)))
+                .collect(),
+        };
+        Config::from_sources(&sources, profile, overrides)
+    }
+
+    fn from_sources(
+        sources: &Sources,
+        profile: Option<&str>,
+        overrides: &[String],
+    ) -> Result<Config> {
+        let mut merged = builtin_defaults();
+        for layer in &sources.files {
+            if layer.exists() {
+                merge(&mut merged, try!(load_migrated(layer, false)));
+            }
         }
 
+        let mut section = merged.get("config").cloned().unwrap_or(Value::Null);
+        if let Some(name) = profile {
+            match merged.get("profiles").and_then(|profiles| profiles.get(name)) {
+                Some(overlay) => merge(&mut section, overlay.clone()),
+                None => return Err(ErrorKind::UnknownProfile(name.to_string()).into()),
+            }
+        }
+
+        if let Some(ref repo_config) = sources.repo_config {
+            let doc = try!(load_migrated(repo_config, false));
+            try!(check_repo_config(repo_config, &doc));
+            merge(&mut section, doc.get("config").cloned().unwrap_or(Value::Null));
+        }
+
+        for &(key, ref raw) in &sources.env {
+            set_path(&mut section, key, override_value(key, raw));
+        }
+
+        for raw in overrides {
+            let mut parts = raw.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(val)) if !key.trim().is_empty() => {
+                    let key = key.trim();
+                    set_path(&mut section, key, override_value(key, val));
+                }
+                _ => return Err(ErrorKind::InvalidOverride(raw.to_string()).into()),
+            }
+        }
+
+        let settings: Settings = try!(serde_path_to_error::deserialize(section).map_err(|e| {
+            let path = match e.path().to_string().as_str() {
+                "." => "config".to_string(),
+                path => format!("config.{}", path),
+            };
+            ErrorKind::InvalidConfigValue(path, e.into_inner().to_string())
+        }));
+
+        if settings.project_keys.is_empty() {
+            return Err(ErrorKind::InvalidConfigValue(
+                "config.project_keys".to_string(),
+                "expected a non-empty list of project keys".to_string(),
+            ).into());
+        }
+
+        // taken before new_issue_defaults moves out of settings
+        let first_project = settings.project_keys[0].clone();
+        let username = settings.username.clone();
+        let defaults = settings.new_issue_defaults;
         Ok(Config {
-            jira_url: jira_url,
-            auth: auth_data,
-            username: username,
-            projects: projects,
-            npc_users: npc_users,
-            open_in_browser: open_in_browser,
-            browser_command: browser_command,
             defaults: Defaults {
-                project_key: default_project_key,
-                assignee: default_assignee,
-                labels: default_labels,
+                project_key: defaults.project_key.unwrap_or(first_project),
+                assignee: defaults.assignee.unwrap_or(username),
+                labels: defaults.labels,
             },
+            jira_url: settings.jira,
+            auth: settings.auth,
+            username: settings.username,
+            projects: settings.project_keys,
+            npc_users: settings.npc_users,
+            open_in_browser: settings.open_in_browser,
+            browser_command: settings.browser_command,
+            branch_template: settings.branch_template,
+            epic_link_field: settings.epic_link_field,
+            field_aliases: settings.field_aliases,
+            http: settings.http,
+            retry: settings.retry,
         })
     }
 
-    pub fn create(path: &Path) -> Result<Config> {
-        print!("Jira url: ");
-        try!(io::stdout().flush()); // need to do this since print! won't flush
-        let mut jira = String::new();
-        io::stdin().read_line(&mut jira).expect("Invalid jira url");
-
-        print!("Username: ");
-        try!(io::stdout().flush()); // need to do this since print! won't flush
-        let mut username = String::new();
-        io::stdin()
-            .read_line(&mut username)
-            .expect("Invalid username");
-
-        let pass = rpassword::prompt_password_stdout("Password: ").unwrap();
-
-        print!("Interrupt project key: ");
-        try!(io::stdout().flush()); // need to do this since print! won't flush
-        let mut project_key = String::new();
-        io::stdin()
-            .read_line(&mut project_key)
-            .expect("Invalid project key");
-
-        print!("Team username (a 'team' user like 'foo-robot'): ");
-        try!(io::stdout().flush()); // need to do this since print! won't flush
-        let mut npc = String::new();
-        io::stdin()
-            .read_line(&mut npc)
-            .expect("Invalid team username");
-
-        let auth = format!("{}:{}", username.trim(), pass.trim());
-        let base64auth = encode(&auth);
-
-        try!(create_config_file(
-            path,
-            jira.trim(),
-            username.trim(),
-            &base64auth,
-            npc.trim(),
-            project_key.trim()
+    /// Interactively creates a config file. An existing file is never
+    /// truncated without asking: a new profile is simply added to it, and
+    /// anything else offers to merge, edit or overwrite the current settings.
+    /// Credentials are checked against the server before anything is written.
+    pub fn create(path: &Path, profile: Option<&str>) -> Result<Config> {
+        let mut existing = None;
+        let mut action = SetupAction::Overwrite;
+
+        if path.exists() {
+            let loaded = Config::new(path, profile, &[]);
+            let profile_is_new = match loaded {
+                Err(ref e) => match *e.kind() {
+                    ErrorKind::UnknownProfile(_) => true,
+                    _ => false,
+                },
+                Ok(_) => false,
+            };
+            existing = loaded.ok();
+
+            action = if profile_is_new {
+                SetupAction::Merge
+            } else {
+                try!(prompt_for_action(path))
+            };
+        }
+
+        match action {
+            SetupAction::Abort => return Err(ErrorKind::SetupAborted.into()),
+            SetupAction::Edit => {
+                try!(edit_in_editor(path));
+                return Config::new(path, profile, &[]);
+            }
+            SetupAction::Overwrite => existing = None,
+            SetupAction::Merge => {}
+        }
+
+        let jira = try!(prompt(
+            "Jira url",
+            existing.as_ref().map(|c| c.jira_url.as_str())
         ));
+        let username = try!(prompt(
+            "Username",
+            existing.as_ref().map(|c| c.username.as_str())
+        ));
+
+        // the stored auth is the old user's, so a new user needs a password
+        let keep_auth = existing
+            .as_ref()
+            .map_or(false, |config| config.username == username);
+        let pass = if keep_auth {
+            rpassword::prompt_password_stdout("Password (blank to keep current): ").unwrap()
+        } else {
+            rpassword::prompt_password_stdout("Password: ").unwrap()
+        };
+        if !keep_auth && existing.is_some() && pass.trim().is_empty() {
+            return Err(ErrorKind::InvalidCredentials(format!(
+                "the username changed to {}, so its password is needed",
+                username
+            )).into());
+        }
+
+        let project_key = try!(prompt(
+            "Interrupt project key",
+            existing.as_ref().map(|c| c.defaults.project_key.as_str())
+        ));
+        let npc = try!(prompt(
+            "Team username (a 'team' user like 'foo-robot')",
+            existing.as_ref().map(|c| c.defaults.assignee.as_str())
+        ));
+
+        let base64auth = match existing {
+            Some(ref config) if keep_auth && pass.trim().is_empty() => config.auth.clone(),
+            _ => encode(&format!("{}:{}", username, pass.trim())),
+        };
+
+        let http = existing
+            .as_ref()
+            .map(|config| config.http.clone())
+            .unwrap_or_default();
+        println!("Checking credentials against {}", jira);
+        if let Err(why) = Jira::with_options(&base64auth, &jira, &http) {
+            return Err(ErrorKind::InvalidCredentials(why.to_string()).into());
+        }
+
+        let section = config_section(&jira, &username, &base64auth, &npc, &project_key);
+
+        match (action, profile) {
+            (SetupAction::Overwrite, None) => try!(create_config_file(path, &section)),
+            (SetupAction::Overwrite, Some(name)) if !path.exists() => {
+                try!(create_config_file(path, &section));
+                try!(write_section(path, Some(name), &section, false));
+            }
+            (SetupAction::Overwrite, profile) => try!(write_section(path, profile, &section, false)),
+            (_, profile) => try!(write_section(path, profile, &section, true)),
+        }
+
+        Config::new(path, profile, &[])
+    }
 
-        Config::new(path)
+    /// Upgrades the config file at `path` to CONFIG_VERSION for good, keeping
+    /// a backup of the old file. False if it was already up to date.
+    pub fn migrate_file(path: &Path) -> Result<bool> {
+        let mut doc = try!(read_yaml(path));
+        let version = try!(migrate(&mut doc));
+        if version < CONFIG_VERSION {
+            try!(save_migrated(path, &doc, version));
+        }
+        Ok(version < CONFIG_VERSION)
+    }
+
+    pub fn projects(&self) -> jql::List {
+        jql::List::strings(&self.projects)
     }
 
-    pub fn projects(&self) -> String {
-        self.projects.join(", ")
+    pub fn npc_users(&self) -> jql::List {
+        jql::List::users(&self.npc_users)
     }
 
-    pub fn npc_users(&self) -> String {
-        self.npc_users.join(", ")
+    pub fn username(&self) -> jql::Value {
+        jql::Value::user(&self.username)
     }
 }
 
-fn create_config_file(
-    path: &Path,
-    jira: &str,
-    username: &str,
-    auth: &str,
-    npc: &str,
-    project_key: &str,
-) -> Result<()> {
-    let mut file = try!(File::create(&path));
-    let content = format!(
-        "# configuration for oh-bother
-config_version: 1
-config:
-  # connectivity settings
-  jira: \"{jira}\"
-  username: \"{username}\"
-  auth: \"{auth}\"
+// where the layers of a config are read from, gathered up front so that
+// tests don't depend on the machine's files and environment
+struct Sources {
+    /// `/etc/ob.yml` and then the user's config, either may be missing.
+    files: Vec<PathBuf>,
+    repo_config: Option<PathBuf>,
+    /// The setting and raw value of every `OB_*` variable that is set.
+    env: Vec<(&'static str, String)>,
+}
 
-  # controls whether or not manipulated issues are opened in the web browser
-  open_in_browser: true
-  browser_command: google-chrome
+// finds the .ob.yml at the root of the git repository we're in, if any
+fn repo_config_path() -> Option<PathBuf> {
+    let cwd = match env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return None,
+    };
 
-  # These projects are used to find issues for commands like 'list' and 'next'
-  project_keys:
-    - \"{project_key}\"
+    for dir in cwd.ancestors() {
+        if dir.join(".git").exists() {
+            return Some(dir.join(REPO_CONFIG_NAME));
+        }
+    }
+    None
+}
 
-  # these users are users for whom a ticket is considered 'fair game' or 'unassigned'
-  npc_users:
-    - Unassigned
-    - \"{npc}\"
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
 
-  new_issue_defaults:
-    project_key: \"{project_key}\"
-    assignee: \"{npc}\"
-    labels:
-      - interrupt
+// the settings a repository's .ob.yml may pin. The file comes with whatever
+// was cloned, so it mustn't decide where requests and credentials go or what
+// gets run.
+const REPO_SETTINGS: &[&str] = &[
+    "project_keys",
+    "new_issue_defaults",
+    "field_aliases",
+    "branch_template",
+];
+
+fn check_repo_config(path: &Path, doc: &Value) -> Result<()> {
+    let root = match *doc {
+        Value::Mapping(ref root) => root,
+        _ => return Err(ErrorKind::InvalidConfig.into()),
+    };
+
+    for (key, value) in root {
+        let rejected = match key.as_str() {
+            Some("config_version") => None,
+            Some("config") => value.as_mapping().and_then(|section| {
+                section
+                    .iter()
+                    .map(|(setting, _)| setting.as_str().unwrap_or(""))
+                    .find(|setting| !REPO_SETTINGS.contains(setting))
+                    .map(|setting| format!("config.{}", setting))
+            }),
+            // the empty mapping the v1 to v2 migration adds is fine
+            Some("profiles") if value.as_mapping().map_or(false, |p| p.is_empty()) => None,
+            other => Some(other.unwrap_or("").to_string()),
+        };
+        if let Some(key) = rejected {
+            return Err(ErrorKind::InvalidConfigValue(
+                format!("{}: {}", path.display(), key),
+                format!(
+                    "a repository's {} can only set {}",
+                    REPO_CONFIG_NAME,
+                    REPO_SETTINGS.join(", ")
+                ),
+            ).into());
+        }
+    }
+    Ok(())
+}
+
+// recursively merges `overlay` into `base`, mappings are merged key by key and
+// anything else in the overlay replaces what was there
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (_, Value::Null) => {}
+        (&mut Value::Mapping(ref mut base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                if let Some(existing) = base.get_mut(&key) {
+                    merge(existing, value);
+                    continue;
+                }
+                base.insert(key, value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// sets a dotted path like `new_issue_defaults.labels`, creating any missing
+// mappings along the way
+fn set_path(section: &mut Value, path: &str, value: Value) {
+    let mut overlay = value;
+    for key in path.rsplit('.') {
+        let mut mapping = Mapping::new();
+        mapping.insert(Value::String(key.to_string()), overlay);
+        overlay = Value::Mapping(mapping);
+    }
+    merge(section, overlay);
+}
+
+// settings from the environment or command line are plain strings, so they
+// are coerced into the shape the setting expects
+fn override_value(path: &str, raw: &str) -> Value {
+    if LIST_SETTINGS.contains(&path) {
+        return Value::Sequence(
+            raw.split(',')
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| Value::String(v.to_string()))
+                .collect(),
+        );
+    }
+
+    if NUMERIC_SETTINGS.contains(&path) {
+        if let Ok(number) = raw.trim().parse::<u64>() {
+            return Value::Number(number.into());
+        }
+    }
+
+    match raw.trim() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        val => Value::String(val.to_string()),
+    }
+}
+
+fn read_yaml(path: &Path) -> Result<Value> {
+    let mut file = try!(File::open(&path));
+    let mut s = String::new();
+    try!(file.read_to_string(&mut s));
+    Ok(try!(serde_yaml::from_str(&s)))
+}
+
+// loads a config file, upgrading it if it was written by an older version.
+// Only setup sets `persist` to write the upgraded file back, everything else
+// makes do with the upgraded copy in memory.
+fn load_migrated(path: &Path, persist: bool) -> Result<Value> {
+    let mut doc = try!(read_yaml(path));
+    let version = try!(migrate(&mut doc));
+
+    if version < CONFIG_VERSION && persist {
+        try!(save_migrated(path, &doc, version));
+    } else if version < CONFIG_VERSION {
+        eprintln!(
+            "warning: {} is at config version {}, run 'ob config migrate' to upgrade it to {}",
+            path.display(),
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    Ok(doc)
+}
+
+// writes an upgraded config over the file, after taking a backup of it
+fn save_migrated(path: &Path, doc: &Value, version: i64) -> Result<()> {
+    let backup = path.with_extension(format!("yml.v{}.bak", version));
+    try!(fs::copy(path, &backup));
+    try!(write_yaml(path, doc));
+    eprintln!(
+        "Migrated {} from config version {} to {} (backup at {})",
+        path.display(),
+        version,
+        CONFIG_VERSION,
+        backup.display()
+    );
+    Ok(())
+}
+
+// upgrades a config document to CONFIG_VERSION in place, returning the
+// version it started out as
+fn migrate(doc: &mut Value) -> Result<i64> {
+    // configs from before versioning are treated as version 1
+    let version = doc.get("config_version")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(1);
+    if version > CONFIG_VERSION {
+        return Err(ErrorKind::UnsupportedConfigVersion(version).into());
+    }
+    if version < 1 {
+        return Err(ErrorKind::InvalidConfigValue(
+            "config_version".to_string(),
+            format!("a version from 1 to {}, got {}", CONFIG_VERSION, version),
+        ).into());
+    }
+    if version == CONFIG_VERSION {
+        return Ok(version);
+    }
+
+    let root = match *doc {
+        Value::Mapping(ref mut root) => root,
+        _ => return Err(ErrorKind::InvalidConfig.into()),
+    };
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        (*migration)(root);
+    }
+    root.insert(
+        Value::String("config_version".to_string()),
+        Value::Number(CONFIG_VERSION.into()),
+    );
+    Ok(version)
+}
+
+fn prompt(label: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", label, default),
+        None => print!("{}: ", label),
+    }
+    try!(io::stdout().flush()); // need to do this since print! won't flush
+    let mut input = String::new();
+    try!(io::stdin().read_line(&mut input));
+
+    let input = input.trim();
+    match default {
+        Some(default) if input.is_empty() => Ok(default.to_string()),
+        _ => Ok(input.to_string()),
+    }
+}
+
+fn prompt_for_action(path: &Path) -> Result<SetupAction> {
+    println!("A config already exists at {}", path.display());
+    loop {
+        let answer = try!(prompt(
+            "[m]erge with it, [e]dit it, [o]verwrite it, or [a]bort?",
+            Some("m")
+        ));
+        match answer.to_lowercase().as_str() {
+            "m" | "merge" => return Ok(SetupAction::Merge),
+            "e" | "edit" => return Ok(SetupAction::Edit),
+            "o" | "overwrite" => return Ok(SetupAction::Overwrite),
+            "a" | "abort" => return Ok(SetupAction::Abort),
+            _ => println!("Please answer m, e, o or a"),
+        }
+    }
+}
+
+fn edit_in_editor(path: &Path) -> Result<()> {
+    let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = try!(Command::new(editor).arg(path).status());
+    if !status.success() {
+        return Err(ErrorKind::Unexpected(format!("editor exited with {}", status)).into());
+    }
+    Ok(())
+}
+
+fn config_section(jira: &str, username: &str, auth: &str, npc: &str, project_key: &str) -> String {
+    format!(
+        "# connectivity settings
+jira: \"{jira}\"
+username: \"{username}\"
+auth: \"{auth}\"
+
+# controls whether or not manipulated issues are opened in the web browser
+open_in_browser: true
+browser_command: google-chrome
+
+# the branch 'ob branch' creates, {{key}}, {{project}} and {{summary-slug}} are filled in
+# branch_template: \"{{key}}-{{summary-slug}}\"
+
+# the custom field holding an issue's epic, it differs between jira instances
+# epic_link_field: customfield_10008
+
+# short names for custom fields, usable as column names with 'ob import'
+# field_aliases:
+#   team: customfield_10200
+
+# These projects are used to find issues for commands like 'list' and 'next'
+project_keys:
+  - \"{project_key}\"
+
+# these users are users for whom a ticket is considered 'fair game' or 'unassigned'
+npc_users:
+  - Unassigned
+  - \"{npc}\"
+
+new_issue_defaults:
+  project_key: \"{project_key}\"
+  assignee: \"{npc}\"
+  labels:
+    - interrupt
+
+# uncomment for corporate proxies, internal CAs or client certificates
+# http:
+#   proxy: \"http://proxy.example.com:3128\"
+#   ca_bundle: ~/certs/internal-ca.pem
+#   client_certificate: ~/certs/me.p12
+#   client_certificate_password: \"\"
+#   connect_timeout: 10
+#   read_timeout: 30
+
+# how often read-only requests are retried on network errors and rate limiting
+# retry:
+#   max_retries: 3
+#   base_delay_ms: 500
+#   max_delay_ms: 30000
 ",
         jira = jira,
         username = username,
         auth = auth,
         npc = npc,
         project_key = project_key
+    )
+}
+
+fn create_config_file(path: &Path, section: &str) -> Result<()> {
+    let mut file = try!(File::create(&path));
+    let indented: Vec<String> = section
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("  {}", line)
+            }
+        })
+        .collect();
+    let content = format!(
+        "# configuration for oh-bother
+config_version: {}
+config:
+{}
+profiles: {{}}
+",
+        CONFIG_VERSION,
+        indented.join("\n")
     );
 
     try!(file.write_all(content.as_bytes()));
     Ok(())
 }
+
+// writes a single section (the top-level config, or a named profile) into an
+// existing config file, leaving the rest of the file's settings alone. The
+// file is written out again from the parsed yaml, which loses its comments
+// and layout, so that needs an ok and the original is kept as a backup.
+fn write_section(path: &Path, profile: Option<&str>, section: &str, merge: bool) -> Result<()> {
+    let backup = path.with_extension("yml.bak");
+    println!(
+        "Saving the settings rewrites {}, which drops its comments and formatting \
+         (the current file is kept at {})",
+        path.display(),
+        backup.display()
+    );
+    let answer = try!(prompt("Continue? [y/n]", Some("y")));
+    if !answer.to_lowercase().starts_with('y') {
+        return Err(ErrorKind::SetupAborted.into());
+    }
+    try!(fs::copy(path, &backup));
+
+    let mut doc = try!(load_migrated(path, true));
+    let fresh: Value = try!(serde_yaml::from_str(section));
+
+    {
+        let root = match doc {
+            Value::Mapping(ref mut root) => root,
+            _ => return Err(ErrorKind::InvalidConfig.into()),
+        };
+
+        let parent = match profile {
+            Some(_) => {
+                let profiles_key = Value::String("profiles".to_string());
+                let has_profiles = match root.get(&profiles_key) {
+                    Some(&Value::Mapping(_)) => true,
+                    _ => false,
+                };
+                if !has_profiles {
+                    root.insert(profiles_key.clone(), Value::Mapping(Mapping::new()));
+                }
+                match root.get_mut(&profiles_key) {
+                    Some(&mut Value::Mapping(ref mut profiles)) => profiles,
+                    _ => return Err(ErrorKind::InvalidConfig.into()),
+                }
+            }
+            None => root,
+        };
+
+        let key = Value::String(profile.unwrap_or("config").to_string());
+        let section = match parent.get(&key) {
+            Some(existing) if merge => merge_section(existing, fresh),
+            _ => fresh,
+        };
+        parent.insert(key, section);
+    }
+
+    write_yaml(path, &doc)
+}
+
+// combines freshly prompted settings with an existing section: connection
+// settings are replaced, lists are unioned, and everything else keeps the
+// value the user already had
+fn merge_section(existing: &Value, fresh: Value) -> Value {
+    let (mut merged, fresh) = match (existing.clone(), fresh) {
+        (Value::Mapping(existing), Value::Mapping(fresh)) => (existing, fresh),
+        (_, fresh) => return fresh,
+    };
+
+    for (key, value) in fresh {
+        let combined = match (key.as_str(), merged.get(&key)) {
+            (Some("jira"), _) | (Some("username"), _) | (Some("auth"), _) | (_, None) => value,
+            (_, Some(&Value::Sequence(ref old))) => {
+                let mut union = old.clone();
+                if let Value::Sequence(new) = value {
+                    for item in new {
+                        if !union.contains(&item) {
+                            union.push(item);
+                        }
+                    }
+                }
+                Value::Sequence(union)
+            }
+            (_, Some(old)) => old.clone(),
+        };
+        merged.insert(key, combined);
+    }
+
+    Value::Mapping(merged)
+}
+
+fn write_yaml(path: &Path, doc: &Value) -> Result<()> {
+    let mut content = try!(serde_yaml::to_string(doc));
+    content.push('\n');
+
+    let mut file = try!(File::create(&path));
+    try!(file.write_all(content.as_bytes()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+    use std::process;
+
+    const USER_CONFIG: &str = "config_version: 2
+config:
+  jira: \"https://jira.example.com/\"
+  username: bob
+  auth: Ym9iOmh1bnRlcjI=
+  project_keys: [FOO]
+profiles:
+  ops:
+    project_keys: [OPS]
+    new_issue_defaults:
+      assignee: ops-robot
+";
+
+    // a scratch directory for one test's config files
+    struct Files(PathBuf);
+
+    impl Files {
+        fn new(test: &str) -> Files {
+            let dir = env::temp_dir().join(format!("ob-config-{}-{}", process::id(), test));
+            fs::create_dir_all(&dir).unwrap();
+            Files(dir)
+        }
+
+        fn write(&self, name: &str, yaml: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, yaml).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn user_only(path: PathBuf) -> Sources {
+        Sources {
+            files: vec![path],
+            repo_config: None,
+            env: Vec::new(),
+        }
+    }
+
+    fn load(sources: &Sources, profile: Option<&str>, overrides: &[&str]) -> Result<Config> {
+        let overrides: Vec<String> = overrides.iter().map(|o| o.to_string()).collect();
+        Config::from_sources(sources, profile, &overrides)
+    }
+
+    fn load_error(result: Result<Config>) -> Error {
+        match result {
+            Ok(_) => panic!("the config loaded"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn errors_name_the_full_path_of_the_setting() {
+        let files = Files::new("paths");
+        let user = files.write(
+            "ob.yml",
+            &USER_CONFIG.replace(
+                "  project_keys: [FOO]\n",
+                "  project_keys: [FOO]\n  new_issue_defaults:\n    labels: 3\n",
+            ),
+        );
+        match *load_error(load(&user_only(user), None, &[])).kind() {
+            ErrorKind::InvalidConfigValue(ref path, _) => {
+                assert_eq!(path, "config.new_issue_defaults.labels")
+            }
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+
+        let user = files.write("ob.yml", USER_CONFIG);
+        match *load_error(load(&user_only(user), None, &["http.read_timeout=soon"])).kind() {
+            ErrorKind::InvalidConfigValue(ref path, _) => {
+                assert_eq!(path, "config.http.read_timeout")
+            }
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn layers_apply_from_defaults_up_to_the_command_line() {
+        let files = Files::new("layers");
+        let system = files.write(
+            "system.yml",
+            "config_version: 2
+config:
+  jira: \"https://jira.example.com/\"
+  auth: Ym9iOmh1bnRlcjI=
+  username: etc
+  project_keys: [ETC]
+",
+        );
+        let user = files.write(
+            "ob.yml",
+            "config_version: 2
+config:
+  username: bob
+  browser_command: user
+profiles:
+  ops:
+    browser_command: profile
+    new_issue_defaults:
+      project_key: PROFILE
+",
+        );
+        let repo = files.write(
+            "repo.yml",
+            "config_version: 2
+config:
+  new_issue_defaults:
+    project_key: REPO
+    assignee: repo
+",
+        );
+        let sources = Sources {
+            files: vec![system, user],
+            repo_config: Some(repo),
+            env: vec![
+                ("new_issue_defaults.assignee", "env".to_string()),
+                ("branch_template", "env".to_string()),
+            ],
+        };
+
+        let config = load(&sources, Some("ops"), &["branch_template=set"]).unwrap();
+        assert_eq!(config.npc_users, vec!["Unassigned"]);
+        assert_eq!(config.projects, vec!["ETC"]);
+        assert_eq!(config.username, "bob");
+        assert_eq!(config.browser_command, "profile");
+        assert_eq!(config.defaults.project_key, "REPO");
+        assert_eq!(config.defaults.assignee, "env");
+        assert_eq!(config.branch_template, "set");
+    }
+
+    #[test]
+    fn profiles_are_only_applied_when_selected() {
+        let files = Files::new("profiles");
+        let sources = user_only(files.write("ob.yml", USER_CONFIG));
+
+        let config = load(&sources, None, &[]).unwrap();
+        assert_eq!(config.projects, vec!["FOO"]);
+        assert_eq!(config.defaults.assignee, "bob");
+
+        let config = load(&sources, Some("ops"), &[]).unwrap();
+        assert_eq!(config.projects, vec!["OPS"]);
+        assert_eq!(config.defaults.project_key, "OPS");
+        assert_eq!(config.defaults.assignee, "ops-robot");
+        assert_eq!(config.username, "bob");
+
+        match *load_error(load(&sources, Some("staging"), &[])).kind() {
+            ErrorKind::UnknownProfile(ref name) => assert_eq!(name, "staging"),
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn env_overrides_are_read_like_the_settings_they_set() {
+        let files = Files::new("env");
+        let mut sources = user_only(files.write("ob.yml", USER_CONFIG));
+        sources.env = vec![
+            ("project_keys", "FOO, BAR,".to_string()),
+            ("open_in_browser", "true".to_string()),
+            ("http.proxy", "http://proxy.example.com:3128".to_string()),
+        ];
+
+        let config = load(&sources, None, &[]).unwrap();
+        assert_eq!(config.projects, vec!["FOO", "BAR"]);
+        assert!(config.open_in_browser);
+        assert_eq!(config.http.proxy, Some("http://proxy.example.com:3128".to_string()));
+    }
+
+    #[test]
+    fn overrides_set_nested_settings() {
+        let mut section: Value =
+            serde_yaml::from_str("new_issue_defaults:\n  assignee: bob\n").unwrap();
+        set_path(
+            &mut section,
+            "new_issue_defaults.labels",
+            override_value("new_issue_defaults.labels", "a, b"),
+        );
+        set_path(&mut section, "http.read_timeout", override_value("http.read_timeout", "30"));
+        let expected: Value = serde_yaml::from_str(
+            "new_issue_defaults:\n  assignee: bob\n  labels: [a, b]\nhttp:\n  read_timeout: 30\n",
+        ).unwrap();
+        assert_eq!(section, expected);
+
+        assert_eq!(override_value("open_in_browser", " false "), Value::Bool(false));
+        assert_eq!(
+            override_value("http.read_timeout", "soon"),
+            Value::String("soon".to_string())
+        );
+        assert_eq!(override_value("username", "bob"), Value::String("bob".to_string()));
+    }
+
+    #[test]
+    fn old_configs_are_only_rewritten_by_migrate_file() {
+        let files = Files::new("migrate");
+        let user = files.write("ob.yml", &USER_CONFIG.replace("config_version: 2", "config_version: 1"));
+        let backup = user.with_extension("yml.v1.bak");
+
+        load(&user_only(user.clone()), None, &[]).unwrap();
+        assert!(fs::read_to_string(&user).unwrap().starts_with("config_version: 1"));
+
+        assert!(Config::migrate_file(&user).unwrap());
+        assert!(fs::read_to_string(&user).unwrap().contains("config_version: 2"));
+        assert!(fs::read_to_string(&backup).unwrap().starts_with("config_version: 1"));
+        assert!(!Config::migrate_file(&user).unwrap());
+    }
+
+    fn migrated(yaml: &str) -> Result<(i64, Value)> {
+        let mut doc: Value = serde_yaml::from_str(yaml).unwrap();
+        let version = try!(migrate(&mut doc));
+        Ok((version, doc))
+    }
+
+    #[test]
+    fn unversioned_configs_are_migrated_from_version_1() {
+        let (version, doc) = migrated("config:\n  username: bob\n").unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(doc.get("config_version").and_then(|v| v.as_i64()), Some(CONFIG_VERSION));
+        assert_eq!(doc.get("profiles"), Some(&Value::Mapping(Mapping::new())));
+        assert_eq!(doc["config"]["username"], Value::String("bob".to_string()));
+    }
+
+    #[test]
+    fn current_configs_are_left_alone() {
+        let yaml = format!("config_version: {}\nconfig: {{}}\n", CONFIG_VERSION);
+        let (version, doc) = migrated(&yaml).unwrap();
+        assert_eq!(version, CONFIG_VERSION);
+        assert_eq!(doc.get("profiles"), None);
+    }
+
+    #[test]
+    fn versions_out_of_range_are_rejected() {
+        for version in &[0, -3] {
+            let yaml = format!("config_version: {}\nconfig: {{}}\n", version);
+            match *migrated(&yaml).unwrap_err().kind() {
+                ErrorKind::InvalidConfigValue(ref path, _) => assert_eq!(path, "config_version"),
+                ref other => panic!("unexpected error: {:?}", other),
+            }
+        }
+
+        let yaml = format!("config_version: {}\n", CONFIG_VERSION + 1);
+        match *migrated(&yaml).unwrap_err().kind() {
+            ErrorKind::UnsupportedConfigVersion(version) => assert_eq!(version, CONFIG_VERSION + 1),
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
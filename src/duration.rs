@@ -0,0 +1,126 @@
+use chrono;
+
+use error::{ErrorKind, Result};
+
+pub const SECONDS_PER_MINUTE: u64 = 60;
+pub const SECONDS_PER_HOUR: u64 = 60 * SECONDS_PER_MINUTE;
+/// Jira's default working time: a day is 8 hours and a week is 5 days.
+pub const SECONDS_PER_DAY: u64 = 8 * SECONDS_PER_HOUR;
+pub const SECONDS_PER_WEEK: u64 = 5 * SECONDS_PER_DAY;
+
+// splits "1h 30m" or "1h30m" into (1, 'h'), (30, 'm')
+fn components(raw: &str) -> Result<Vec<(u64, char)>> {
+    let invalid = || ErrorKind::InvalidDuration(raw.to_string());
+    let mut parts = Vec::new();
+    let mut number = String::new();
+    for c in raw.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else if c.is_whitespace() {
+            if !number.is_empty() {
+                return Err(invalid().into());
+            }
+        } else {
+            let amount = number.parse::<u64>().map_err(|_| invalid())?;
+            parts.push((amount, c.to_ascii_lowercase()));
+            number.clear();
+        }
+    }
+    if parts.is_empty() || !number.is_empty() {
+        return Err(invalid().into());
+    }
+    Ok(parts)
+}
+
+/// Parses a time spent in Jira's notation, e.g. "1h 30m", "2d" or "1w",
+/// into seconds. Days and weeks are working days and weeks.
+pub fn parse(raw: &str) -> Result<u64> {
+    let mut seconds = 0;
+    for (amount, unit) in components(raw)? {
+        seconds += amount * match unit {
+            'w' => SECONDS_PER_WEEK,
+            'd' => SECONDS_PER_DAY,
+            'h' => SECONDS_PER_HOUR,
+            'm' => SECONDS_PER_MINUTE,
+            _ => return Err(ErrorKind::InvalidDuration(raw.to_string()).into()),
+        };
+    }
+    if seconds == 0 {
+        return Err(ErrorKind::InvalidDuration(raw.to_string()).into());
+    }
+    Ok(seconds)
+}
+
+/// Parses a span of calendar time like "1w" or "3d" for looking back in
+/// time, where unlike `parse` a day has 24 hours and a week 7 days.
+pub fn parse_calendar(raw: &str) -> Result<chrono::Duration> {
+    let mut span = chrono::Duration::zero();
+    for (amount, unit) in components(raw)? {
+        let amount = amount as i64;
+        span = span + match unit {
+            'w' => chrono::Duration::weeks(amount),
+            'd' => chrono::Duration::days(amount),
+            'h' => chrono::Duration::hours(amount),
+            'm' => chrono::Duration::minutes(amount),
+            _ => return Err(ErrorKind::InvalidDuration(raw.to_string()).into()),
+        };
+    }
+    Ok(span)
+}
+
+/// Formats seconds the way Jira shows time spent, e.g. "1d 2h 30m".
+pub fn format(seconds: u64) -> String {
+    let mut remaining = seconds;
+    let mut parts = Vec::new();
+    for &(size, unit) in &[
+        (SECONDS_PER_WEEK, "w"),
+        (SECONDS_PER_DAY, "d"),
+        (SECONDS_PER_HOUR, "h"),
+        (SECONDS_PER_MINUTE, "m"),
+    ] {
+        if remaining >= size {
+            parts.push(format!("{}{}", remaining / size, unit));
+            remaining %= size;
+        }
+    }
+    if parts.is_empty() {
+        return "0m".to_string();
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_jira_durations() {
+        assert_eq!(parse("1h 30m").unwrap(), 90 * 60);
+        assert_eq!(parse("1h30m").unwrap(), 90 * 60);
+        assert_eq!(parse("45m").unwrap(), 45 * 60);
+        assert_eq!(parse("2d").unwrap(), 16 * 60 * 60);
+        assert_eq!(parse("1w 1d").unwrap(), 48 * 60 * 60);
+        assert_eq!(parse(" 2H ").unwrap(), 2 * 60 * 60);
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        for raw in &["", "90", "1x", "h", "1 h", "0m", "1h 30"] {
+            assert!(parse(raw).is_err(), "{} should not parse", raw);
+        }
+    }
+
+    #[test]
+    fn calendar_spans_use_whole_days() {
+        assert_eq!(parse_calendar("1w").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_calendar("2d 12h").unwrap(), chrono::Duration::hours(60));
+    }
+
+    #[test]
+    fn formats_like_jira() {
+        assert_eq!(format(90 * 60), "1h 30m");
+        assert_eq!(format(SECONDS_PER_WEEK + SECONDS_PER_DAY), "1w 1d");
+        assert_eq!(format(0), "0m");
+        assert_eq!(format(parse("3d 4h 5m").unwrap()), "3d 4h 5m");
+    }
+}
@@ -1,12 +1,11 @@
 use base64::DecodeError;
-use clap;
 use eprompt;
-use hyper;
+use reqwest;
 use serde_json;
+use serde_yaml;
 use std::io;
 use std;
 use url::ParseError;
-use yaml_rust::scanner::ScanError;
 
 error_chain! {
     links {
@@ -19,8 +18,8 @@ error_chain! {
         IoError(io::Error);
         JsonError(serde_json::Error);
         ParseError(ParseError);
-        RequestError(hyper::error::Error);
-        ScanError(ScanError);
+        RequestError(reqwest::Error);
+        YamlError(serde_yaml::Error);
     }
 
     errors {
@@ -28,51 +27,125 @@ error_chain! {
             description("invalid config file")
             display("invalid config file")
         }
+        MissingConfig(path: String) {
+            description("missing config file")
+            display("config file {} doesn't exist", path)
+        }
+        InvalidConfigValue(path: String, reason: String) {
+            description("invalid config value")
+            display("invalid config file: {}: {}", path, reason)
+        }
+        InvalidOverride(raw: String) {
+            description("invalid config override")
+            display("config overrides must look like key=value, got '{}'", raw)
+        }
+        InvalidCredentials(message: String) {
+            description("invalid credentials")
+            display("could not log in with the given credentials: {}", message)
+        }
+        AuthenticationFailed(status: String) {
+            description("authentication failed")
+            display("jira rejected the login request: {}", status)
+        }
+        SetupAborted {
+            description("setup aborted")
+            display("setup aborted, the existing config was left untouched")
+        }
+        UnsupportedConfigVersion(version: i64) {
+            description("unsupported config version")
+            display("config_version {} is newer than this version of ob supports", version)
+        }
+        UnknownProfile(name: String) {
+            description("unknown profile")
+            display("no profile named '{}' in the config file", name)
+        }
+        ValidationFailed {
+            description("config validation failed")
+            display("the config file refers to things jira doesn't know about")
+        }
+        EmptyResult(query: String) {
+            description("empty result")
+            display("the query \"{}\" returned no issues", query)
+        }
+        InvalidSelection(input: String) {
+            description("invalid selection")
+            display("'{}' is not one of the listed issues", input)
+        }
+        NotFound(what: String) {
+            description("not found")
+            display("{} not found", what)
+        }
+        InvalidNumber(arg: String, raw: String) {
+            description("invalid number")
+            display("--{} must be a whole number, got '{}'", arg, raw)
+        }
+        InvalidDuration(raw: String) {
+            description("invalid duration")
+            display("'{}' is not a duration like 1h 30m, 2d or 1w", raw)
+        }
+        InvalidDate(raw: String) {
+            description("invalid date")
+            display("'{}' is not a date like 2017-12-07 09:30 or a time like 09:30", raw)
+        }
+        NoActiveIssue {
+            description("no active issue")
+            display("no interrupt is in progress, pass an issue key")
+        }
+        NoTransition(issue: String, statuses: String) {
+            description("no matching transition")
+            display("{} can't be moved to {} from its current status", issue, statuses)
+        }
+        NotCached(what: String) {
+            description("not cached")
+            display("{} is not in the offline cache, run the command once without --offline", what)
+        }
+        Offline(command: String) {
+            description("not available offline")
+            display("'{}' needs to talk to jira and can't be used with --offline", command)
+        }
+        InvalidIssueFile(reason: String) {
+            description("invalid issue file")
+            display("invalid issue file: {}", reason)
+        }
+        InvalidImportFile(reason: String) {
+            description("invalid import file")
+            display("invalid import file: {}", reason)
+        }
+        ImportFailed(failed: usize, total: usize) {
+            description("import failed")
+            display("{} of {} rows could not be imported", failed, total)
+        }
+        BulkFailed(failed: usize, total: usize) {
+            description("bulk change failed")
+            display("{} of {} issues could not be changed", failed, total)
+        }
+        InvalidLabelChange(raw: String) {
+            description("invalid label change")
+            display("'{}' should be +label to add a label or -label to remove it", raw)
+        }
+        InvalidLabel(label: String) {
+            description("invalid label")
+            display("'{}' is not a valid label, labels can't be empty or contain spaces", label)
+        }
+        NoSubtaskType(project: String) {
+            description("no subtask issue type")
+            display("{} has no subtask issue type, so it can't have subtasks", project)
+        }
+        InvalidLink(reason: String) {
+            description("invalid link")
+            display("can't link: {}", reason)
+        }
+        GitError(message: String) {
+            description("git failed")
+            display("git: {}", message)
+        }
+        JiraError(status: u16, message: String) {
+            description("jira returned an error")
+            display("jira returned HTTP {}: {}", status, message)
+        }
         Unexpected(message: String) {
             description("unexpected result")
             display("unexpected result: {}", message)
         }
     }
 }
-
-pub trait UnwrapOrExit<T>
-where
-    Self: Sized,
-{
-    fn unwrap_or_else<F>(self, f: F) -> T
-    where
-        F: FnOnce() -> T;
-
-    fn unwrap_or_exit(self, message: &str) -> T {
-        let err = clap::Error::with_description(message, clap::ErrorKind::InvalidValue);
-        self.unwrap_or_else(|| err.exit())
-    }
-}
-
-impl<T> UnwrapOrExit<T> for Option<T> {
-    fn unwrap_or_else<F>(self, f: F) -> T
-    where
-        F: FnOnce() -> T,
-    {
-        self.unwrap_or_else(f)
-    }
-}
-
-impl<T> UnwrapOrExit<T> for Result<T> {
-    fn unwrap_or_else<F>(self, f: F) -> T
-    where
-        F: FnOnce() -> T,
-    {
-        self.unwrap_or_else(|_| f())
-    }
-
-    fn unwrap_or_exit(self, message: &str) -> T {
-        self.unwrap_or_else(|e| {
-            let err = clap::Error::with_description(
-                &format!("{}: {}", message, e),
-                clap::ErrorKind::InvalidValue,
-            );
-            err.exit()
-        })
-    }
-}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use error::{ErrorKind, Result};
+use issue::Issue;
+
+/// How long the summary part of a branch name may get.
+const MAX_SLUG_LEN: usize = 50;
+
+/// Marks hooks written by `install_hook`, so they can be replaced safely.
+const HOOK_MARKER: &str = "installed by ob";
+
+const PREPARE_COMMIT_MSG: &str = r#"#!/bin/sh
+# installed by ob: adds the jira issue named in the branch to commit messages
+case "$2" in merge|squash) exit 0 ;; esac
+branch=$(git symbolic-ref --short -q HEAD) || exit 0
+key=$(printf '%s\n' "$branch" | tr '/' '\n' | grep -E '^[A-Z][A-Z0-9_]+-[0-9]+([^A-Za-z0-9]|$)' |
+    head -n 1 | grep -oE '^[A-Z][A-Z0-9_]+-[0-9]+')
+[ -n "$key" ] || exit 0
+grep -q "^Issue: $key\$" "$1" && exit 0
+git interpret-trailers --in-place --trailer "Issue: $key" "$1"
+"#;
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(ErrorKind::GitError(message).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Lowercases and replaces anything that doesn't belong in a branch name
+/// with single dashes, e.g. "The build is broken!" -> "the-build-is-broken".
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    // cut at a word boundary where possible
+    if slug.len() > MAX_SLUG_LEN {
+        let cut = match slug[..MAX_SLUG_LEN].rfind('-') {
+            Some(idx) if idx > 0 => idx,
+            _ => MAX_SLUG_LEN,
+        };
+        slug.truncate(cut);
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// Fills in a branch template. `{key}`, `{project}` and `{summary-slug}` are
+/// replaced with the issue's key, project key and slugified summary.
+pub fn branch_name(template: &str, issue: &Issue) -> String {
+    let project = issue.key.split('-').next().unwrap_or("");
+    template
+        .replace("{key}", &issue.key)
+        .replace("{project}", project)
+        .replace("{summary-slug}", &slugify(&issue.summary))
+}
+
+/// Finds the issue key a branch is named after, e.g. `FOO-12-rotate-certs`
+/// or `bob/FOO-12`. The key has to start the name or follow a `/`, and be in
+/// one of the given projects, so that things like `fix-UTF-8` don't count.
+pub fn issue_key_from_branch(branch: &str, projects: &[String]) -> Option<String> {
+    branch
+        .split('/')
+        .filter_map(leading_issue_key)
+        .find(|key| {
+            let project = &key[..key.rfind('-').unwrap()];
+            projects.iter().any(|p| p.eq_ignore_ascii_case(project))
+        })
+}
+
+// `ABC-123` at the start of the text, not followed by more digits or letters
+fn leading_issue_key(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() || !chars[0].is_ascii_uppercase() {
+        return None;
+    }
+    let mut pos = 0;
+    while pos < chars.len()
+        && (chars[pos].is_ascii_uppercase() || chars[pos].is_ascii_digit() || chars[pos] == '_')
+    {
+        pos += 1;
+    }
+    if pos < 2 || pos >= chars.len() || chars[pos] != '-' {
+        return None;
+    }
+    let digits = pos + 1;
+    let mut end = digits;
+    while end < chars.len() && chars[end].is_ascii_digit() {
+        end += 1;
+    }
+    if end == digits || (end < chars.len() && chars[end].is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(chars[..end].iter().collect())
+}
+
+/// The branch checked out in the current directory, `None` outside of a git
+/// repository or with a detached HEAD.
+pub fn current_branch() -> Option<String> {
+    git(&["symbolic-ref", "--short", "-q", "HEAD"])
+        .ok()
+        .filter(|branch| !branch.is_empty())
+}
+
+/// Checks out the branch, creating it first if it doesn't exist yet. Returns
+/// whether it was created.
+pub fn checkout(branch: &str) -> Result<bool> {
+    let exists = git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("refs/heads/{}", branch),
+    ]).is_ok();
+    if exists {
+        git(&["checkout", branch])?;
+    } else {
+        git(&["checkout", "-b", branch])?;
+    }
+    Ok(!exists)
+}
+
+/// Installs a `prepare-commit-msg` hook that adds an `Issue: KEY` trailer
+/// taken from the branch name. A hook that wasn't written by ob is only
+/// replaced when `force` is set.
+pub fn install_hook(force: bool) -> Result<PathBuf> {
+    let hooks = PathBuf::from(git(&["rev-parse", "--git-path", "hooks"])?);
+    let path = hooks.join("prepare-commit-msg");
+
+    if path.exists() && !force {
+        let mut existing = String::new();
+        File::open(&path)?.read_to_string(&mut existing)?;
+        if !existing.contains(HOOK_MARKER) {
+            return Err(ErrorKind::GitError(format!(
+                "{} already exists, use --force to replace it",
+                path.display()
+            )).into());
+        }
+    }
+
+    fs::create_dir_all(&hooks)?;
+    File::create(&path)?.write_all(PREPARE_COMMIT_MSG.as_bytes())?;
+    make_executable(&path)?;
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &PathBuf) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &PathBuf) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify_keeps_words_and_dashes() {
+        assert_eq!(slugify("The build is broken!"), "the-build-is-broken");
+        assert_eq!(slugify("  [prod] Disk full: build-03  "), "prod-disk-full-build-03");
+        assert_eq!(slugify("Ünïcode only"), "n-code-only");
+        assert_eq!(slugify(&"word ".repeat(20)).len(), 49);
+    }
+
+    #[test]
+    fn branch_names_follow_the_template() {
+        let issue = Issue {
+            summary: "Rotate the TLS certs".to_string(),
+            ..Issue::example("FOO-12")
+        };
+        assert_eq!(
+            branch_name("{key}-{summary-slug}", &issue),
+            "FOO-12-rotate-the-tls-certs"
+        );
+        assert_eq!(
+            branch_name("interrupts/{project}/{key}", &issue),
+            "interrupts/FOO/FOO-12"
+        );
+    }
+
+    #[test]
+    fn finds_issue_keys_in_branch_names() {
+        let projects = vec!["FOO".to_string(), "OPS_2".to_string()];
+        let key = |branch| issue_key_from_branch(branch, &projects);
+        assert_eq!(key("FOO-12-rotate-certs"), Some("FOO-12".to_string()));
+        assert_eq!(key("bob/OPS_2-7"), Some("OPS_2-7".to_string()));
+        assert_eq!(key("UTF-8/FOO-3"), Some("FOO-3".to_string()));
+        assert_eq!(key("fix-FOO-3"), None);
+        assert_eq!(key("UTF-8-cleanup"), None);
+        assert_eq!(key("BAR-1-not-ours"), None);
+        assert_eq!(key("FOO-1a"), None);
+        assert_eq!(key("master"), None);
+        assert_eq!(key("aFOO-1"), None);
+    }
+}
@@ -0,0 +1,575 @@
+use csv;
+use serde_json::{Map, Value};
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use config::{Config, Defaults};
+use error::{ErrorKind, Result};
+use jira_data::{CreateMeta, FieldMeta, IssueTypeMeta};
+
+/// One issue to create for `ob import`, keyed by column name. Empty cells are
+/// left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// The line in a csv file, or the position in a yml list.
+    pub number: usize,
+    pub cells: BTreeMap<String, Value>,
+}
+
+impl Row {
+    // columns are matched without regard to case
+    fn cell(&self, column: &str) -> Option<&Value> {
+        self.cells
+            .iter()
+            .find(|&(name, _)| name.trim().eq_ignore_ascii_case(column))
+            .map(|(_, value)| value)
+    }
+
+    fn text(&self, column: &str) -> Option<String> {
+        self.cell(column).map(text)
+    }
+}
+
+fn text(value: &Value) -> String {
+    match *value {
+        Value::String(ref text) => text.trim().to_string(),
+        ref other => other.to_string(),
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match *value {
+        Value::Null => true,
+        Value::String(ref text) => text.trim().is_empty(),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Yaml,
+}
+
+impl Format {
+    /// The format for a `--format` value or a file extension.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "yml" | "yaml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Format::from_name)
+    }
+}
+
+fn invalid<E: ToString>(why: E) -> ErrorKind {
+    ErrorKind::InvalidImportFile(why.to_string())
+}
+
+pub fn read(text: &str, format: Format) -> Result<Vec<Row>> {
+    match format {
+        Format::Csv => read_csv(text),
+        Format::Yaml => read_yaml(text),
+    }
+}
+
+// the header names the columns, and counts towards the line numbers
+fn read_csv(text: &str) -> Result<Vec<Row>> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(invalid)?
+        .iter()
+        .map(|header| header.trim().to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        let number = record
+            .position()
+            .map_or(rows.len() + 2, |position| position.line() as usize);
+        let cells = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, cell)| (header.clone(), Value::String(cell.trim().to_string())))
+            .filter(|&(_, ref value)| !is_blank(value))
+            .collect();
+        rows.push(Row {
+            number: number,
+            cells: cells,
+        });
+    }
+    Ok(rows)
+}
+
+// a list of mappings, using the same names as the csv columns
+fn read_yaml(text: &str) -> Result<Vec<Row>> {
+    let entries: Vec<BTreeMap<String, Value>> = serde_yaml::from_str(text).map_err(invalid)?;
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, cells)| Row {
+            number: index + 1,
+            cells: cells
+                .into_iter()
+                .filter(|&(_, ref value)| !is_blank(value))
+                .collect(),
+        })
+        .collect())
+}
+
+/// The projects the rows create issues in, which is what `createmeta` needs
+/// to be asked about.
+pub fn projects(rows: &[Row], default: &str) -> Vec<String> {
+    let mut projects: Vec<String> = rows.iter()
+        .map(|row| {
+            row.text("project")
+                .unwrap_or_else(|| default.to_string())
+                .to_uppercase()
+        })
+        .collect();
+    projects.sort();
+    projects.dedup();
+    projects
+}
+
+/// Turns rows into the `fields` of create requests, checking them against
+/// what `createmeta` says can be set for the row's project and issue type.
+pub struct Importer<'a> {
+    meta: &'a CreateMeta,
+    aliases: &'a BTreeMap<String, String>,
+    defaults: &'a Defaults,
+    epic_link_field: &'a str,
+}
+
+impl<'a> Importer<'a> {
+    pub fn new(meta: &'a CreateMeta, config: &'a Config) -> Importer<'a> {
+        Importer {
+            meta: meta,
+            aliases: &config.field_aliases,
+            defaults: &config.defaults,
+            epic_link_field: &config.epic_link_field,
+        }
+    }
+
+    /// The fields for the row's create request, or everything that's wrong
+    /// with the row.
+    pub fn fields(&self, row: &Row) -> ::std::result::Result<Value, Vec<String>> {
+        // the project and issue type decide which fields can be set
+        let project_key = row.text("project")
+            .unwrap_or_else(|| self.defaults.project_key.clone());
+        let project = match self.meta
+            .projects
+            .iter()
+            .find(|project| project.key.eq_ignore_ascii_case(&project_key))
+        {
+            Some(project) => project,
+            None => {
+                return Err(vec![format!(
+                    "project {} doesn't exist or you can't create issues in it",
+                    project_key
+                )])
+            }
+        };
+
+        let type_name = row.text("issuetype").or_else(|| row.text("type"));
+        let subtask = row.cell("parent").is_some();
+        let issuetype = match type_name {
+            Some(ref type_name) => project
+                .issuetypes
+                .iter()
+                .find(|issuetype| issuetype.name.eq_ignore_ascii_case(type_name)),
+            None => project.default_issuetype(subtask),
+        };
+        let issuetype = match issuetype {
+            Some(issuetype) => issuetype,
+            None => {
+                let names: Vec<&str> = project
+                    .issuetypes
+                    .iter()
+                    .map(|issuetype| issuetype.name.as_str())
+                    .collect();
+                let wanted = match type_name {
+                    Some(type_name) => format!("issue type {}", type_name),
+                    None if subtask => "subtask issue type".to_string(),
+                    None => "issue type for issues without a parent".to_string(),
+                };
+                return Err(vec![format!(
+                    "{} has no {}, use one of {}",
+                    project.key,
+                    wanted,
+                    names.join(", ")
+                )]);
+            }
+        };
+
+        let mut fields = Map::new();
+        fields.insert("project".to_string(), object("key", &project.key));
+        fields.insert("issuetype".to_string(), object("name", &issuetype.name));
+
+        let mut problems = Vec::new();
+        // fields that were given but are wrong aren't missing as well
+        let mut rejected = Vec::new();
+        for (column, value) in &row.cells {
+            let id = self.field_id(column);
+            if id == "project" || id == "issuetype" {
+                continue;
+            }
+            let (id, field) = match find_field(issuetype, &id, column) {
+                Some(found) => found,
+                None => {
+                    problems.push(format!(
+                        "{} isn't a field of a {} in {}",
+                        column, issuetype.name, project.key
+                    ));
+                    continue;
+                }
+            };
+            match convert(field, value) {
+                Ok(value) => {
+                    fields.insert(id.clone(), value);
+                }
+                Err(why) => {
+                    problems.push(format!("{}: {}", column, why));
+                    rejected.push(id);
+                }
+            }
+        }
+
+        // the same defaults as `ob new`, where the issue type has the field
+        if !fields.contains_key("assignee") && issuetype.fields.contains_key("assignee") {
+            fields.insert(
+                "assignee".to_string(),
+                object("name", &self.defaults.assignee),
+            );
+        }
+        if !fields.contains_key("labels") && issuetype.fields.contains_key("labels")
+            && !self.defaults.labels.is_empty()
+        {
+            let labels = self.defaults
+                .labels
+                .iter()
+                .map(|label| Value::String(label.clone()))
+                .collect();
+            fields.insert("labels".to_string(), Value::Array(labels));
+        }
+
+        for (id, field) in &issuetype.fields {
+            let missing = !fields.contains_key(id) && !rejected.contains(&id);
+            if field.required && !field.hasDefaultValue && missing {
+                problems.push(format!("{} is required", field.name));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(Value::Object(fields))
+        } else {
+            Err(problems)
+        }
+    }
+
+    // an alias from the config, one of the names `ob new` uses, or else the
+    // column is taken to be the field's id
+    fn field_id(&self, column: &str) -> String {
+        let column = column.trim().to_lowercase();
+        if let Some((_, id)) = self.aliases
+            .iter()
+            .find(|&(alias, _)| alias.eq_ignore_ascii_case(&column))
+        {
+            return id.clone();
+        }
+        match column.as_str() {
+            "epic" => self.epic_link_field.to_string(),
+            "type" => "issuetype".to_string(),
+            _ => column,
+        }
+    }
+}
+
+// by id, or by the name jira shows for it like "Story Points"
+fn find_field<'m>(
+    issuetype: &'m IssueTypeMeta,
+    id: &str,
+    column: &str,
+) -> Option<(&'m String, &'m FieldMeta)> {
+    issuetype
+        .fields
+        .iter()
+        .find(|&(field_id, _)| field_id == id)
+        .or_else(|| {
+            issuetype
+                .fields
+                .iter()
+                .find(|&(_, field)| field.name.eq_ignore_ascii_case(column.trim()))
+        })
+}
+
+fn object(key: &str, value: &str) -> Value {
+    let mut object = Map::new();
+    object.insert(key.to_string(), Value::String(value.to_string()));
+    Value::Object(object)
+}
+
+// puts a cell into the shape jira expects for the field's type
+fn convert(field: &FieldMeta, value: &Value) -> ::std::result::Result<Value, String> {
+    if field.schema.field_type != "array" {
+        return convert_one(field, &field.schema.field_type, value);
+    }
+
+    let items = match *value {
+        Value::Array(ref items) => items.clone(),
+        // csv cells hold lists as comma separated values
+        Value::String(ref text) => text.split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(|item| Value::String(item.to_string()))
+            .collect(),
+        ref other => vec![other.clone()],
+    };
+    let item_type = field.schema.items.as_ref().map_or("string", |items| items.as_str());
+    let converted: ::std::result::Result<Vec<Value>, String> = items
+        .iter()
+        .map(|item| convert_one(field, item_type, item))
+        .collect();
+    converted.map(Value::Array)
+}
+
+fn convert_one(
+    field: &FieldMeta,
+    kind: &str,
+    value: &Value,
+) -> ::std::result::Result<Value, String> {
+    let text = match *value {
+        Value::String(ref text) => text.trim().to_string(),
+        Value::Number(ref number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        // yml can spell out jira's shape itself, e.g. {name: High}
+        ref other => return Ok(other.clone()),
+    };
+
+    let text = if field.allowedValues.is_empty() {
+        text
+    } else {
+        let labels: Vec<&str> = field
+            .allowedValues
+            .iter()
+            .filter_map(|allowed| allowed.label())
+            .collect();
+        match labels.iter().find(|label| label.eq_ignore_ascii_case(&text)) {
+            Some(label) => label.to_string(),
+            None => return Err(format!("'{}' isn't one of {}", text, labels.join(", "))),
+        }
+    };
+
+    Ok(match kind {
+        "number" => {
+            if let Ok(number) = text.parse::<i64>() {
+                Value::Number(number.into())
+            } else {
+                match text.parse::<f64>().ok().and_then(::serde_json::Number::from_f64) {
+                    Some(number) => Value::Number(number),
+                    None => return Err(format!("'{}' isn't a number", text)),
+                }
+            }
+        }
+        "option" => object("value", &text),
+        "user" | "priority" | "component" | "version" | "resolution" | "securitylevel" => {
+            object("name", &text)
+        }
+        "issuelink" => object("key", &text.to_uppercase()),
+        _ => Value::String(text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn meta() -> CreateMeta {
+        serde_json::from_str(include_str!("../tests/fixtures/createmeta.json")).unwrap()
+    }
+
+    fn defaults() -> Defaults {
+        Defaults {
+            project_key: "FOO".to_string(),
+            assignee: "foo-robot".to_string(),
+            labels: vec!["interrupt".to_string()],
+        }
+    }
+
+    fn importer<'a>(
+        meta: &'a CreateMeta,
+        aliases: &'a BTreeMap<String, String>,
+        defaults: &'a Defaults,
+    ) -> Importer<'a> {
+        Importer {
+            meta: meta,
+            aliases: aliases,
+            defaults: defaults,
+            epic_link_field: "customfield_10008",
+        }
+    }
+
+    fn row(cells: &[(&str, &str)]) -> Row {
+        Row {
+            number: 2,
+            cells: cells
+                .iter()
+                .map(|&(column, value)| (column.to_string(), Value::String(value.to_string())))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn csv_rows_are_numbered_by_line() {
+        let rows = read(
+            "summary, labels ,assignee\n\
+             Review access for alice,\"access-review, q3\",\n\
+             \"Review access\nfor bob\",,bob\n",
+            Format::Csv,
+        ).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].number, 2);
+        assert_eq!(rows[0].text("labels"), Some("access-review, q3".to_string()));
+        assert_eq!(rows[0].cell("assignee"), None);
+        assert_eq!(rows[1].number, 3);
+        assert_eq!(rows[1].text("Summary"), Some("Review access\nfor bob".to_string()));
+
+        assert!(read("summary,labels\na,b,c\n", Format::Csv).is_err());
+    }
+
+    #[test]
+    fn yml_rows_are_numbered_by_position() {
+        let rows = read(
+            "- summary: Review access for alice\n  labels: [access-review]\n  points: 3\n\
+             - summary: Review access for bob\n  assignee: ~\n",
+            Format::Yaml,
+        ).unwrap();
+        assert_eq!(rows[1].number, 2);
+        assert_eq!(rows[0].cells["points"], 3);
+        assert_eq!(rows[1].cell("assignee"), None);
+        assert!(read("summary: not a list\n", Format::Yaml).is_err());
+    }
+
+    #[test]
+    fn projects_are_listed_once() {
+        let rows = vec![row(&[("project", "bar")]), row(&[]), row(&[("Project", "FOO")])];
+        assert_eq!(projects(&rows, "FOO"), vec!["BAR", "FOO"]);
+    }
+
+    #[test]
+    fn columns_are_mapped_to_fields() {
+        let meta = meta();
+        let defaults = defaults();
+        let mut aliases = BTreeMap::new();
+        aliases.insert("Team".to_string(), "customfield_10200".to_string());
+        let importer = importer(&meta, &aliases, &defaults);
+
+        let fields = importer
+            .fields(&row(&[
+                ("Summary", "Review access for alice"),
+                ("type", "task"),
+                ("team", "security"),
+                ("Story Points", "3"),
+                ("labels", "access-review, q3"),
+            ]))
+            .unwrap();
+        assert_eq!(fields["project"]["key"], "FOO");
+        assert_eq!(fields["issuetype"]["name"], "Task");
+        assert_eq!(fields["summary"], "Review access for alice");
+        assert_eq!(fields["customfield_10200"]["value"], "Security");
+        assert_eq!(fields["customfield_10002"], 3);
+        assert_eq!(fields["labels"][1], "q3");
+        assert_eq!(fields["assignee"]["name"], "foo-robot");
+
+        let fields = importer
+            .fields(&row(&[("summary", "Rebuild"), ("parent", "foo-1")]))
+            .unwrap();
+        assert_eq!(fields["issuetype"]["name"], "Sub-task");
+        assert_eq!(fields["parent"]["key"], "FOO-1");
+        // sub-tasks can't be assigned or labelled on this screen
+        assert!(fields.get("assignee").is_none());
+        assert!(fields.get("labels").is_none());
+
+        let fields = importer.fields(&row(&[("summary", "Broken")])).unwrap();
+        assert_eq!(fields["issuetype"]["name"], "Bug");
+        assert_eq!(fields["labels"][0], "interrupt");
+    }
+
+    #[test]
+    fn issue_types_default_to_what_the_project_has() {
+        let mut meta = meta();
+        {
+            let issuetypes = &mut meta.projects[0].issuetypes;
+            issuetypes.retain(|issuetype| issuetype.name != "Bug");
+            issuetypes[1].name = "Subtask".to_string();
+        }
+        let defaults = defaults();
+        let aliases = BTreeMap::new();
+        let fields = importer(&meta, &aliases, &defaults)
+            .fields(&row(&[("summary", "Broken"), ("customfield_10200", "Security")]))
+            .unwrap();
+        assert_eq!(fields["issuetype"]["name"], "Task");
+        let fields = importer(&meta, &aliases, &defaults)
+            .fields(&row(&[("summary", "Rebuild"), ("parent", "FOO-1")]))
+            .unwrap();
+        assert_eq!(fields["issuetype"]["name"], "Subtask");
+
+        meta.projects[0].issuetypes.retain(|issuetype| !issuetype.subtask);
+        let problems = importer(&meta, &aliases, &defaults)
+            .fields(&row(&[("summary", "Rebuild"), ("parent", "FOO-1")]))
+            .unwrap_err();
+        assert_eq!(problems, vec!["FOO has no subtask issue type, use one of Task"]);
+    }
+
+    #[test]
+    fn problems_are_collected_per_row() {
+        let meta = meta();
+        let defaults = defaults();
+        let aliases = BTreeMap::new();
+        let importer = importer(&meta, &aliases, &defaults);
+
+        let problems = importer
+            .fields(&row(&[("project", "BAR"), ("summary", "x")]))
+            .unwrap_err();
+        assert_eq!(
+            problems,
+            vec!["project BAR doesn't exist or you can't create issues in it"]
+        );
+
+        let problems = importer
+            .fields(&row(&[("type", "Story"), ("summary", "x")]))
+            .unwrap_err();
+        assert_eq!(
+            problems,
+            vec!["FOO has no issue type Story, use one of Bug, Task, Sub-task"]
+        );
+
+        let problems = importer
+            .fields(&row(&[
+                ("type", "Task"),
+                ("priority", "High"),
+                ("Story Points", "lots"),
+                ("customfield_10200", "Sales"),
+            ]))
+            .unwrap_err();
+        assert_eq!(
+            problems,
+            vec![
+                "Story Points: 'lots' isn't a number",
+                "customfield_10200: 'Sales' isn't one of Platform, Security",
+                "priority isn't a field of a Task in FOO",
+                "Summary is required",
+            ]
+        );
+    }
+}
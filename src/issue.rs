@@ -1,13 +1,18 @@
-use hyper::Url;
+use url::Url;
 use prettytable::Table;
 use prettytable::row::Row;
 use prettytable::cell::Cell;
 use prettytable::format;
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::process::Command;
+use std::slice;
 
-use jira_data::{IssueResponse, IssueResponseList};
+use error::Result;
+use jira_data::{AttachmentFields, IssueResponse, IssueResponseList, LinkedIssue, RemoteLink};
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Issue {
     pub self_url: String,
     pub key: String,
@@ -17,6 +22,123 @@ pub struct Issue {
     pub reporter: String,
     pub labels: Vec<String>,
     pub browse_url: String,
+    /// Only filled in when looking up a single issue.
+    #[serde(default)]
+    pub links: Vec<Link>,
+    #[serde(default)]
+    pub parent: Option<IssueRef>,
+    #[serde(default)]
+    pub subtasks: Vec<IssueRef>,
+    /// The key of the epic the issue belongs to.
+    #[serde(default)]
+    pub epic: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// A file attached to an issue.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub size: u64,
+    pub author: String,
+    pub created: String,
+    /// Where the file can be downloaded from, see `Jira::download`.
+    pub content_url: String,
+}
+
+impl Attachment {
+    pub fn from_attachment_fields(fields: &AttachmentFields) -> Attachment {
+        Attachment {
+            id: fields.id.clone(),
+            filename: fields.filename.clone(),
+            size: fields.size,
+            author: fields.author.display_name(),
+            created: fields.created.clone(),
+            content_url: fields.content.clone(),
+        }
+    }
+}
+
+/// An issue to create, see `Jira::create_issue`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NewIssue {
+    pub project: String,
+    pub summary: String,
+    pub description: String,
+    pub assignee: String,
+    pub labels: Vec<String>,
+    /// Creates a subtask of this issue.
+    pub parent: Option<String>,
+    /// Adds the issue to this epic.
+    pub epic: Option<String>,
+    /// Any other fields, as they appear in a create request.
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Another issue as shown next to an issue, like its parent or a subtask.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IssueRef {
+    pub key: String,
+    pub summary: String,
+    pub status: String,
+}
+
+impl IssueRef {
+    fn from_linked_issue(linked: &LinkedIssue) -> IssueRef {
+        let (summary, status) = match linked.fields {
+            Some(ref fields) => (
+                fields.summary.clone(),
+                fields
+                    .status
+                    .as_ref()
+                    .map_or("Unknown".to_string(), |status| status.name.clone()),
+            ),
+            None => (String::new(), "Unknown".to_string()),
+        };
+        IssueRef {
+            key: linked.key.clone(),
+            summary: summary,
+            status: status,
+        }
+    }
+}
+
+impl fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} ({})", self.key, self.summary, self.status)
+    }
+}
+
+/// A link from an issue to another issue, or to a web page like a PR.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Link {
+    /// How the issue relates to the target, e.g. "blocks" or "is blocked by".
+    pub relation: String,
+    /// The other issue's key, or the url.
+    pub target: String,
+    pub title: String,
+}
+
+impl Link {
+    pub fn from_remote_link(remote: &RemoteLink) -> Link {
+        Link {
+            relation: "links to".to_string(),
+            target: remote.object.url.clone(),
+            title: remote.object.title.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.title.is_empty() || self.title == self.target {
+            write!(f, "{} {}", self.relation, self.target)
+        } else {
+            write!(f, "{} {} ({})", self.relation, self.target, self.title)
+        }
+    }
 }
 
 impl fmt::Display for Issue {
@@ -54,6 +176,46 @@ impl Issue {
             },
             labels: response.fields.labels.clone(),
             browse_url: browse_url,
+            links: response
+                .fields
+                .issuelinks
+                .iter()
+                .filter_map(|link| {
+                    // the link's other end is whichever issue is set
+                    let (relation, other) = match (&link.outwardIssue, &link.inwardIssue) {
+                        (&Some(ref other), _) => (&link.link_type.outward, other),
+                        (_, &Some(ref other)) => (&link.link_type.inward, other),
+                        _ => return None,
+                    };
+                    Some(Link {
+                        relation: relation.clone(),
+                        target: other.key.clone(),
+                        title: other
+                            .fields
+                            .as_ref()
+                            .map_or(String::new(), |fields| fields.summary.clone()),
+                    })
+                })
+                .collect(),
+            parent: response
+                .fields
+                .parent
+                .as_ref()
+                .map(IssueRef::from_linked_issue),
+            subtasks: response
+                .fields
+                .subtasks
+                .iter()
+                .map(IssueRef::from_linked_issue)
+                .collect(),
+            // the epic link is a custom field, see `Jira::issue`
+            epic: None,
+            attachments: response
+                .fields
+                .attachment
+                .iter()
+                .map(Attachment::from_attachment_fields)
+                .collect(),
         }
     }
 
@@ -82,6 +244,12 @@ impl Issue {
         map
     }
 
+    /// Opens the issue's browse url with the given command, e.g. `xdg-open`.
+    pub fn open_in_browser(&self, browser_command: &str) -> Result<()> {
+        Command::new(browser_command).arg(&self.browse_url).output()?;
+        Ok(())
+    }
+
     pub fn print_tty(&self, force_colorize: bool) {
         let mut table = Table::new();
 
@@ -121,12 +289,79 @@ impl Issue {
             ]));
         }
 
+        if !self.attachments.is_empty() {
+            let names: Vec<&str> = self.attachments
+                .iter()
+                .map(|attachment| attachment.filename.as_str())
+                .collect();
+            table.add_row(Row::new(vec![
+                Cell::new(&format!("Attachments ({})", names.len())),
+                Cell::new(names.join("\n").as_str()),
+            ]));
+        }
+
+        if let Some(ref epic) = self.epic {
+            table.add_row(Row::new(vec![Cell::new("Epic"), Cell::new(epic.as_str())]));
+        }
+
+        if let Some(ref parent) = self.parent {
+            table.add_row(Row::new(vec![
+                Cell::new("Parent"),
+                Cell::new(&parent.to_string()),
+            ]));
+        }
+
+        if !self.subtasks.is_empty() {
+            let subtasks: Vec<String> = self.subtasks.iter().map(|task| task.to_string()).collect();
+            table.add_row(Row::new(vec![
+                Cell::new("Subtasks"),
+                Cell::new(subtasks.join("\n").as_str()),
+            ]));
+        }
+
+        if !self.links.is_empty() {
+            let links: Vec<String> = self.links.iter().map(|link| link.to_string()).collect();
+            table.add_row(Row::new(vec![
+                Cell::new("Links"),
+                Cell::new(links.join("\n").as_str()),
+            ]));
+        }
+
         table.print_tty(force_colorize)
     }
 }
 
+#[cfg(test)]
+impl Issue {
+    /// An open issue for tests, with "summary of KEY" as its summary. Change
+    /// what the test looks at with `Issue { status: .., ..Issue::example(key) }`.
+    pub fn example(key: &str) -> Issue {
+        Issue {
+            self_url: String::new(),
+            key: key.to_string(),
+            summary: format!("summary of {}", key),
+            status: "Open".to_string(),
+            assignee: "Foo Robot".to_string(),
+            reporter: "Alice".to_string(),
+            labels: Vec::new(),
+            browse_url: String::new(),
+            links: Vec::new(),
+            parent: None,
+            subtasks: Vec::new(),
+            epic: None,
+            attachments: Vec::new(),
+        }
+    }
+}
+
 pub struct IssueVec(Vec<Issue>);
 
+impl From<Vec<Issue>> for IssueVec {
+    fn from(issues: Vec<Issue>) -> IssueVec {
+        IssueVec(issues)
+    }
+}
+
 impl fmt::Display for IssueVec {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.as_table().fmt(f)
@@ -183,4 +418,27 @@ impl IssueVec {
     pub fn get(&self, idx: usize) -> Option<&Issue> {
         self.0.get(idx)
     }
+
+    pub fn iter(&self) -> slice::Iter<Issue> {
+        self.0.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<Issue> {
+        self.0
+    }
+
+    /// How many of the issues have each label, the most used first.
+    pub fn label_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for label in self.0.iter().flat_map(|issue| issue.labels.iter()) {
+            *counts.entry(label).or_insert(0) += 1;
+        }
+        let mut counts: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(label, count)| (label.to_string(), count))
+            .collect();
+        // the sort is stable, so ties stay in alphabetical order
+        counts.sort_by(|a, b| b.1.cmp(&a.1));
+        counts
+    }
 }
@@ -0,0 +1,227 @@
+use serde_json;
+use serde_json::Value;
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use error::{ErrorKind, Result};
+
+/// An issue described in a file for `ob new --from-file` or `--stdin`.
+/// Anything left out comes from the command line or the config's defaults.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct IssueFile {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub project: Option<String>,
+    pub assignee: Option<String>,
+    pub labels: Option<Vec<String>>,
+    pub parent: Option<String>,
+    pub epic: Option<String>,
+    /// Any other jira fields, as they appear in a create request, e.g.
+    /// `priority: {name: High}`.
+    #[serde(default)]
+    pub fields: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Markdown,
+}
+
+impl Format {
+    /// The format for a `--format` value or a file extension.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "yml" | "yaml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            "md" | "markdown" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Format::from_name)
+    }
+
+    /// Guesses the format of piped input from its first line that isn't
+    /// blank: `{` starts json, `key:` starts yaml and a heading starts
+    /// markdown. `---` is front matter if a second `---` closes it, and
+    /// otherwise the start of a yaml document. Anything else needs
+    /// `--format`, since plain text could be meant as either.
+    pub fn sniff(text: &str) -> Option<Format> {
+        let mut lines = text
+            .lines()
+            .map(|line| line.trim_right())
+            .filter(|line| !line.trim().is_empty());
+        let first = lines.next()?;
+        if first.trim_left().starts_with('{') {
+            Some(Format::Json)
+        } else if first.starts_with('#') {
+            Some(Format::Markdown)
+        } else if first == "---" {
+            let rest: Vec<&str> = lines.collect();
+            if rest.contains(&"---") {
+                Some(Format::Markdown)
+            } else if rest.first().map_or(false, |line| is_mapping_key(line)) {
+                Some(Format::Yaml)
+            } else {
+                None
+            }
+        } else if is_mapping_key(first) {
+            Some(Format::Yaml)
+        } else {
+            None
+        }
+    }
+}
+
+// a top level `key:` or `key: value` line
+fn is_mapping_key(line: &str) -> bool {
+    match line.find(':') {
+        Some(end) => {
+            let key = &line[..end];
+            let rest = &line[end + 1..];
+            !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                && (rest.is_empty() || rest.starts_with(' '))
+        }
+        None => false,
+    }
+}
+
+fn invalid<E: ToString>(why: E) -> ErrorKind {
+    ErrorKind::InvalidIssueFile(why.to_string())
+}
+
+pub fn parse(text: &str, format: Format) -> Result<IssueFile> {
+    match format {
+        Format::Yaml => serde_yaml::from_str(text).map_err(|why| invalid(why).into()),
+        Format::Json => serde_json::from_str(text).map_err(|why| invalid(why).into()),
+        Format::Markdown => parse_markdown(text),
+    }
+}
+
+// markdown may start with yaml front matter between two `---` lines, after
+// that the first heading is the summary and everything else the description
+fn parse_markdown(text: &str) -> Result<IssueFile> {
+    let mut lines = text.lines().peekable();
+
+    let mut file = IssueFile::default();
+    if lines.peek().map(|line| line.trim_right()) == Some("---") {
+        lines.next();
+        let mut front_matter = Vec::new();
+        loop {
+            match lines.next() {
+                Some(line) if line.trim_right() == "---" => break,
+                Some(line) => front_matter.push(line),
+                None => return Err(invalid("the front matter is missing its closing ---").into()),
+            }
+        }
+        if front_matter.iter().any(|line| !line.trim().is_empty()) {
+            file = serde_yaml::from_str(&front_matter.join("\n")).map_err(invalid)?;
+        }
+    }
+
+    let mut body = Vec::new();
+    let mut heading = None;
+    for line in lines {
+        if heading.is_none() && line.starts_with('#') {
+            let title = line.trim_left_matches('#').trim();
+            if !title.is_empty() {
+                heading = Some(title.to_string());
+                continue;
+            }
+        }
+        body.push(line);
+    }
+
+    // the front matter has the last word
+    if file.summary.is_none() {
+        file.summary = heading;
+    }
+    if file.description.is_none() {
+        let description = body.join("\n").trim().to_string();
+        if !description.is_empty() {
+            file.description = Some(description);
+        }
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_heading_is_the_summary() {
+        let file = parse(
+            "---\nlabels: [disk, build]\nassignee: bob\nfields:\n  priority:\n    name: High\n---\n\n\
+             # Disk full on build-03\n\n/var is at 100%.\n\n## Graph\n\nsee attached\n",
+            Format::Markdown,
+        ).unwrap();
+        assert_eq!(file.summary, Some("Disk full on build-03".to_string()));
+        assert_eq!(
+            file.description,
+            Some("/var is at 100%.\n\n## Graph\n\nsee attached".to_string())
+        );
+        assert_eq!(file.labels, Some(vec!["disk".to_string(), "build".to_string()]));
+        assert_eq!(file.assignee, Some("bob".to_string()));
+        assert_eq!(file.fields["priority"]["name"], "High");
+    }
+
+    #[test]
+    fn markdown_without_front_matter_or_heading() {
+        let file = parse("# Broken\n", Format::Markdown).unwrap();
+        assert_eq!(file.summary, Some("Broken".to_string()));
+        assert_eq!(file.description, None);
+
+        let file = parse("just some text", Format::Markdown).unwrap();
+        assert_eq!(file.summary, None);
+        assert_eq!(file.description, Some("just some text".to_string()));
+
+        assert!(parse("---\nlabels: [a]\n# Broken\n", Format::Markdown).is_err());
+    }
+
+    #[test]
+    fn yaml_and_json_files() {
+        let yaml = parse("summary: Broken\nproject: BAR\nepic: BAR-1\n", Format::Yaml).unwrap();
+        assert_eq!(yaml.summary, Some("Broken".to_string()));
+        assert_eq!(yaml.project, Some("BAR".to_string()));
+        assert_eq!(yaml.epic, Some("BAR-1".to_string()));
+
+        let json = parse(
+            "{\"summary\": \"Broken\", \"labels\": [], \"fields\": {\"customfield_1\": 3}}",
+            Format::Json,
+        ).unwrap();
+        assert_eq!(json.labels, Some(Vec::new()));
+        assert_eq!(json.fields["customfield_1"], 3);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(parse("sumary: Broken\n", Format::Yaml).is_err());
+    }
+
+    #[test]
+    fn formats_by_name_and_content() {
+        assert_eq!(Format::from_path(Path::new("alert.YML")), Some(Format::Yaml));
+        assert_eq!(Format::from_path(Path::new("alert.md")), Some(Format::Markdown));
+        assert_eq!(Format::from_path(Path::new("alert")), None);
+        assert_eq!(Format::sniff("  {\"summary\": \"x\"}"), Some(Format::Json));
+        assert_eq!(Format::sniff("# x"), Some(Format::Markdown));
+        assert_eq!(Format::sniff("---\nlabels: [a]\n---\nx"), Some(Format::Markdown));
+        assert_eq!(Format::sniff("---\nsummary: Broken\nlabels: [a]\n"), Some(Format::Yaml));
+        assert_eq!(Format::sniff("---\njust some text\n"), None);
+        assert_eq!(Format::sniff("\nsummary: Broken\nepic: BAR-1\n"), Some(Format::Yaml));
+        assert_eq!(Format::sniff("fields:\n  priority: {name: High}\n"), Some(Format::Yaml));
+        assert_eq!(Format::sniff("just some text"), None);
+        assert_eq!(Format::sniff("note: see https://example.com"), Some(Format::Yaml));
+        assert_eq!(Format::sniff("https://example.com is down"), None);
+        assert_eq!(Format::sniff(""), None);
+    }
+}
@@ -1,25 +1,34 @@
 use base64::decode;
-use hyper::Client;
-use hyper::Url;
-use hyper::client::{IntoUrl, RequestBuilder};
-use hyper::header::{ContentType, Cookie, CookiePair, Headers};
-use hyper::mime::{Attr, Mime, SubLevel, TopLevel, Value};
+use chrono::{DateTime, Local};
 use serde_json;
-use std::io::Read;
+use std::cmp;
+use std::thread;
+use std::time::Duration;
+use url::Url;
 
+use client::{HttpOptions, HttpTransport, Method, Request, Response, Transport};
+use config::Config;
 use error::{ErrorKind, Result};
-use issue::{Issue, IssueVec};
-use jira_data::{AuthRequest, AuthResponse, CreateIssueRequest, CreateIssueResponse, IssueResponse,
-                JQLQuery};
+use issue::{Attachment, Issue, IssueVec, Link, NewIssue};
+use jira_data::{AssignRequest, AttachmentFields, AuthRequest, AuthResponse, BulkCreateRequest,
+                BulkCreateResponse, BulkIssueUpdate, CommentRequest, CreateIssueRequest,
+                CreateIssueResponse, CreateMeta, ErrorResponse, IssueKey, IssueLinkRequest,
+                IssueResponse, IssueResponseList, JQLQuery, LabelUpdate, LinkTypeName, RemoteLink,
+                RemoteObject, Transition, TransitionId, TransitionList, TransitionRequest,
+                UpdateIssueRequest, UpdateLabels, UserFields, Worklog, WorklogList, WorklogRequest,
+                DEFAULT_ISSUE_TYPE};
 
-struct AuthedClient {
-    client: Client,
-    headers: Headers,
+/// The timestamp format jira expects for things like a worklog's start.
+pub const JIRA_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%z";
+
+#[derive(Clone)]
+struct AuthedClient<T: Transport> {
+    transport: T,
+    cookie: String,
 }
 
-impl AuthedClient {
-    pub fn new(auth: &str, base_url: &Url) -> Result<AuthedClient> {
-        let client = Client::new();
+impl<T: Transport> AuthedClient<T> {
+    pub fn new(transport: T, auth: &str, base_url: &Url) -> Result<AuthedClient<T>> {
         let auth_url = base_url.join("rest/auth/1/session")?;
 
         let whole_key = String::from_utf8(decode(auth)?)?;
@@ -32,93 +41,296 @@ impl AuthedClient {
             password: password,
         };
 
-        let mut headers = Headers::new();
-        headers.set(ContentType(Mime(
-            TopLevel::Application,
-            SubLevel::Json,
-            vec![(Attr::Charset, Value::Utf8)],
-        )));
-
         let body = serde_json::to_string(&auth_body)?;
-        let mut res = client
-            .post(auth_url)
-            .headers(headers.clone())
-            .body(body.as_str())
-            .send()?;
-        let mut response_body = String::new();
-        res.read_to_string(&mut response_body)?;
-        let auth_response: AuthResponse = serde_json::from_str(response_body.as_str())?;
-
-        headers.set(Cookie(vec![
-            CookiePair::new(auth_response.session.name, auth_response.session.value),
-        ]));
+        let res = transport.send_logged(Request::new(Method::Post, auth_url).json(body))?;
+        if !res.is_success() {
+            return Err(ErrorKind::AuthenticationFailed(format!("HTTP {}", res.status)).into());
+        }
+        let auth_response: AuthResponse = serde_json::from_slice(&res.body)?;
 
         Ok(AuthedClient {
-            client: client,
-            headers: headers,
+            transport: transport,
+            cookie: format!(
+                "{}={}",
+                auth_response.session.name, auth_response.session.value
+            ),
         })
     }
 
-    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
-        self.client.post(url).headers(self.headers.clone())
+    pub fn send(&self, request: Request) -> Result<Response> {
+        self.transport
+            .send_logged(request.header("Cookie", self.cookie.as_str()))
+    }
+}
+
+// turns a non-2xx response into an error carrying whatever jira said about it
+fn check(res: Response) -> Result<Response> {
+    if res.is_success() {
+        return Ok(res);
+    }
+
+    let message = match serde_json::from_slice::<ErrorResponse>(&res.body) {
+        Ok(ref err) if !err.is_empty() => err.message(),
+        _ => res.text(),
+    };
+    Err(ErrorKind::JiraError(res.status, message).into())
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    30_000
+}
+
+/// How read-only requests are retried when the network or jira is having a
+/// bad moment, read from the `retry` section of the config file.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, 0 disables retrying
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every retry after that
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+    /// Upper bound for any single delay, including one asked for by jira
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: default_max_retries(),
+            base_delay_ms: default_base_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // exponential backoff, unless jira told us how long to wait
+    fn delay(&self, retry: u32, retry_after: Option<u64>) -> Duration {
+        let millis = match retry_after {
+            Some(seconds) => seconds.saturating_mul(1000),
+            None => self.base_delay_ms
+                .saturating_mul(1u64.checked_shl(retry).unwrap_or(u64::max_value())),
+        };
+        Duration::from_millis(cmp::min(millis, self.max_delay_ms))
     }
+}
 
-    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
-        self.client.get(url).headers(self.headers.clone())
+// rate limiting and the errors proxies and load balancers hand out while jira
+// is restarting are worth another try, anything else is jira's final answer
+fn is_retryable_status(status: u16) -> bool {
+    match status {
+        429 | 502 | 503 | 504 => true,
+        _ => false,
     }
 }
 
-pub struct Jira {
-    client: AuthedClient,
+// only the delay-seconds form of Retry-After, the date form falls back to the
+// regular backoff
+fn retry_after(res: &Response) -> Option<u64> {
+    res.header("Retry-After")
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos()) / 1_000_000
+}
+
+/// How many issues jira creates in a single bulk request.
+pub const BULK_CREATE_LIMIT: usize = 50;
+
+/// The custom field holding an issue's epic on a stock Jira server. It's
+/// different on every instance, hence `epic_link_field` in the config.
+pub const DEFAULT_EPIC_LINK_FIELD: &str = "customfield_10008";
+
+/// A logged in client. Clones share the session, so each thread can have its
+/// own.
+#[derive(Clone)]
+pub struct Jira<T: Transport = HttpTransport> {
+    client: AuthedClient<T>,
     base_url: Url,
-    debug: bool,
+    retry: RetryPolicy,
+    epic_link_field: String,
+}
+
+impl Jira<HttpTransport> {
+    pub fn new(auth: &str, base_url: &str) -> Result<Jira<HttpTransport>> {
+        Jira::with_options(auth, base_url, &HttpOptions::default())
+    }
+
+    pub fn with_options(
+        auth: &str,
+        base_url: &str,
+        options: &HttpOptions,
+    ) -> Result<Jira<HttpTransport>> {
+        Jira::with_transport(HttpTransport::new(options)?, auth, base_url)
+    }
+
+    /// Connects using the url, credentials, http and retry settings from the
+    /// config.
+    pub fn from_config(config: &Config) -> Result<Jira<HttpTransport>> {
+        let jira = Jira::with_options(&config.auth, &config.jira_url, &config.http)?;
+        Ok(jira
+            .retry_policy(config.retry.clone())
+            .epic_link_field(&config.epic_link_field))
+    }
 }
 
-impl Jira {
-    pub fn new(auth: &str, base_url: &str, debug: bool) -> Result<Jira> {
+impl<T: Transport> Jira<T> {
+    pub fn with_transport(transport: T, auth: &str, base_url: &str) -> Result<Jira<T>> {
         let url = Url::parse(base_url)?;
-        let client = AuthedClient::new(auth, &url)?;
+        let client = AuthedClient::new(transport, auth, &url)?;
         Ok(Jira {
             client: client,
             base_url: url,
-            debug: debug,
+            retry: RetryPolicy::default(),
+            epic_link_field: DEFAULT_EPIC_LINK_FIELD.to_string(),
         })
     }
 
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Jira<T> {
+        self.retry = retry;
+        self
+    }
+
+    pub fn epic_link_field(mut self, field: &str) -> Jira<T> {
+        self.epic_link_field = field.to_string();
+        self
+    }
+
+    // sends a request that is safe to repeat, retrying on network errors and
+    // the statuses in is_retryable_status
+    fn send_with_retry(&self, request: Request) -> Result<Response> {
+        let attempts = self.retry.max_retries + 1;
+        let mut retry = 0;
+        loop {
+            let result = self.client.send(request.clone());
+            let attempt = retry + 1;
+            let retry_after = match result {
+                Ok(ref res) if is_retryable_status(res.status) && attempt < attempts => {
+                    debug!(
+                        "attempt {}/{}: {} {} returned HTTP {}",
+                        attempt,
+                        attempts,
+                        request.method.as_str(),
+                        request.url,
+                        res.status
+                    );
+                    retry_after(res)
+                }
+                Err(ref why) if attempt < attempts => {
+                    debug!(
+                        "attempt {}/{}: {} {} failed: {}",
+                        attempt,
+                        attempts,
+                        request.method.as_str(),
+                        request.url,
+                        why
+                    );
+                    None
+                }
+                _ => return result,
+            };
+
+            let delay = self.retry.delay(retry, retry_after);
+            debug!("retrying in {}ms", duration_millis(delay));
+            thread::sleep(delay);
+            retry += 1;
+        }
+    }
+
+    fn get(&self, url: Url) -> Result<Response> {
+        self.send_with_retry(Request::new(Method::Get, url))
+    }
+
+    fn post(&self, url: Url, body: String) -> Result<Response> {
+        self.client.send(Request::new(Method::Post, url).json(body))
+    }
+
+    fn put(&self, url: Url, body: String) -> Result<Response> {
+        self.client.send(Request::new(Method::Put, url).json(body))
+    }
+
+    /// Runs a search, fetching page after page until every matching issue is
+    /// in.
     pub fn query(&self, query: &str) -> Result<IssueVec> {
-        let url = self.base_url.join("rest/api/2/search")?;
-        let q = JQLQuery::new(query);
-        let body = serde_json::to_string(&q)?;
-        let mut res = self.client.post(url).body(body.as_str()).send()?;
-        let mut response_body = String::new();
-        res.read_to_string(&mut response_body)?;
-        let data = serde_json::from_str(response_body.as_str())?;
-        Ok(Issue::issues_from_response(&data))
+        let all = self.search(JQLQuery::new(query))?;
+        // turned around once, over every page, so the order holds across pages
+        Ok(Issue::issues_from_response(&all))
     }
 
-    pub fn create_issue(
-        &self,
-        project_key: &str,
-        summary: &str,
-        description: &str,
-        assignee: &str,
-        labels: &Vec<String>,
-        debug: bool
-    ) -> Result<Issue> {
-        let url = self.base_url.join("rest/api/2/issue")?;
-        let request = CreateIssueRequest::new(project_key, summary, description, assignee, labels);
-        let body = serde_json::to_string(&request)?;
+    /// The issues a search finds along with all of their worklogs, oldest
+    /// issue first. Only issues with more worklogs than a search sends need a
+    /// request of their own.
+    pub fn query_worklogs(&self, query: &str) -> Result<Vec<(Issue, Vec<Worklog>)>> {
+        let all = self.search(JQLQuery::new(query).with_field("worklog"))?;
+        let mut issues = Vec::new();
+        for response in all.issues.into_iter().rev() {
+            let issue = Issue::from_issue_response(&response);
+            let worklogs = match response.fields.worklog {
+                Some(list) if list.worklogs.len() >= list.total => list.worklogs,
+                _ => self.worklogs(&issue.key)?,
+            };
+            issues.push((issue, worklogs));
+        }
+        Ok(issues)
+    }
+
+    fn search(&self, query: JQLQuery) -> Result<IssueResponseList> {
+        let url = self.base_url.join("rest/api/2/search")?;
+        let mut all = IssueResponseList::default();
+        loop {
+            let q = query.clone().starting_at(all.issues.len());
+            let body = serde_json::to_string(&q)?;
+            // a search is a POST, but it doesn't change anything so it can be
+            // retried like a GET
+            let request = Request::new(Method::Post, url.clone()).json(body);
+            let res = check(self.send_with_retry(request)?)?;
+            let page: IssueResponseList = serde_json::from_slice(&res.body)?;
 
-        if self.debug {
-            println!("{}", body.as_str());
+            // jira may send fewer than maxResults, only the total says when
+            // we're done
+            let fetched = all.issues.len() + page.issues.len();
+            let done = page.issues.is_empty() || fetched >= page.total;
+            all.issues.extend(page.issues);
+            if done {
+                return Ok(all);
+            }
         }
+    }
 
-        let mut res = self.client.post(url).body(body.as_str()).send()?;
-        let mut response_body = String::new();
-        res.read_to_string(&mut response_body)?;
-        if debug {
-            println!("{}", response_body);
+    pub fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue> {
+        let url = self.base_url.join("rest/api/2/issue")?;
+        // subtasks need one of the project's subtask types, and what that's
+        // called differs between instances
+        let issuetype = match new_issue.parent {
+            Some(_) if !new_issue.fields.contains_key("issuetype") => {
+                self.subtask_type(&new_issue.project)?
+            }
+            _ => DEFAULT_ISSUE_TYPE.to_string(),
+        };
+        let request = CreateIssueRequest::new(new_issue, &issuetype);
+        let mut request = serde_json::to_value(&request)?;
+        for (name, value) in &new_issue.fields {
+            request["fields"][name.as_str()] = value.clone();
+        }
+        if let Some(ref epic) = new_issue.epic {
+            request["fields"][self.epic_link_field.as_str()] =
+                serde_json::Value::String(epic.clone());
         }
+        let body = serde_json::to_string(&request)?;
+
+        let res = check(self.post(url, body)?)?;
+        let response_body = res.text();
         let response: serde_json::Result<CreateIssueResponse> =
             serde_json::from_str(response_body.as_str());
         match response {
@@ -132,17 +344,930 @@ impl Jira {
         }
     }
 
+    fn subtask_type(&self, project_key: &str) -> Result<String> {
+        let meta = self.create_meta(&[project_key.to_string()])?;
+        meta.projects
+            .iter()
+            .find(|project| project.key.eq_ignore_ascii_case(project_key))
+            .and_then(|project| project.default_issuetype(true))
+            .map(|issuetype| issuetype.name.clone())
+            .ok_or_else(|| ErrorKind::NoSubtaskType(project_key.to_string()).into())
+    }
+
+    /// The fields that can be set on new issues in these projects, for every
+    /// issue type.
+    pub fn create_meta(&self, project_keys: &[String]) -> Result<CreateMeta> {
+        let mut url = self.base_url.join("rest/api/2/issue/createmeta")?;
+        url.query_pairs_mut()
+            .append_pair("projectKeys", &project_keys.join(","))
+            .append_pair("expand", "projects.issuetypes.fields");
+        let res = check(self.get(url)?)?;
+        Ok(serde_json::from_slice(&res.body)?)
+    }
+
+    /// Creates an issue for each of the `fields` objects, in batches of
+    /// `BULK_CREATE_LIMIT`. Every entry gets the new issue's key or the
+    /// reason jira rejected it, in the same order.
+    pub fn create_issues(
+        &self,
+        fields: &[serde_json::Value],
+    ) -> Result<Vec<::std::result::Result<String, String>>> {
+        let url = self.base_url.join("rest/api/2/issue/bulk")?;
+        let mut results = Vec::new();
+        for batch in fields.chunks(BULK_CREATE_LIMIT) {
+            let request = BulkCreateRequest {
+                issueUpdates: batch
+                    .iter()
+                    .map(|fields| BulkIssueUpdate {
+                        fields: fields.clone(),
+                    })
+                    .collect(),
+            };
+            let res = self.post(url.clone(), serde_json::to_string(&request)?)?;
+
+            // jira answers 400 as soon as one issue fails, the others are
+            // still created
+            let parsed: serde_json::Result<BulkCreateResponse> =
+                serde_json::from_slice(&res.body);
+            let response = match parsed {
+                Ok(response) if res.is_success() || !response.errors.is_empty() => response,
+                _ => {
+                    let res = check(res)?;
+                    return Err(ErrorKind::Unexpected(format!(
+                        "Jira's bulk create response made no sense: {}",
+                        res.text()
+                    )).into());
+                }
+            };
+
+            let mut created = response.issues.into_iter();
+            for index in 0..batch.len() {
+                let failure = response
+                    .errors
+                    .iter()
+                    .find(|error| error.failedElementNumber == index);
+                results.push(match failure {
+                    Some(error) => Err(error.elementErrors.message()),
+                    None => match created.next() {
+                        Some(issue) => Ok(issue.key),
+                        None => Err("Jira didn't say what happened to it".to_string()),
+                    },
+                });
+            }
+        }
+        Ok(results)
+    }
+
     pub fn issue(&self, issue_key: &str) -> Result<Issue> {
         let url = self.base_url
             .join(&format!("rest/api/2/issue/{}", issue_key))?;
-        let mut res = self.client.get(url).send()?;
-        let mut response_body = String::new();
-        res.read_to_string(&mut response_body)?;
-        let response: serde_json::Result<IssueResponse> =
-            serde_json::from_str(response_body.as_str());
+        let res = self.get(url)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        let res = check(res)?;
+        let raw: serde_json::Value = serde_json::from_slice(&res.body)?;
+        // the epic link's field name depends on the instance, so it can't be
+        // part of IssueFields
+        let epic = raw["fields"][self.epic_link_field.as_str()]
+            .as_str()
+            .map(|epic| epic.to_string());
+        let response: serde_json::Result<IssueResponse> = serde_json::from_value(raw);
         match response {
-            Ok(r) => Ok(Issue::from_issue_response(&r)),
+            Ok(r) => {
+                let mut issue = Issue::from_issue_response(&r);
+                issue.epic = epic;
+                Ok(issue)
+            }
             Err(e) => Err(ErrorKind::Unexpected(format!("Issue {} not found {}", issue_key, e)).into()),
         }
     }
+
+    /// Logs `seconds` of work against an issue, starting at `started` (or
+    /// now).
+    pub fn add_worklog(
+        &self,
+        issue_key: &str,
+        seconds: u64,
+        comment: Option<&str>,
+        started: Option<DateTime<Local>>,
+    ) -> Result<()> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/worklog", issue_key))?;
+        let request = WorklogRequest {
+            timeSpentSeconds: seconds,
+            started: started
+                .unwrap_or_else(Local::now)
+                .format(JIRA_TIME_FORMAT)
+                .to_string(),
+            comment: comment.map(|comment| comment.to_string()),
+        };
+        let res = self.post(url, serde_json::to_string(&request)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        check(res)?;
+        Ok(())
+    }
+
+    pub fn add_comment(&self, issue_key: &str, body: &str) -> Result<()> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/comment", issue_key))?;
+        let request = CommentRequest {
+            body: body.to_string(),
+        };
+        let res = self.post(url, serde_json::to_string(&request)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        check(res)?;
+        Ok(())
+    }
+
+    pub fn assign(&self, issue_key: &str, username: &str) -> Result<()> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/assignee", issue_key))?;
+        let request = AssignRequest {
+            name: username.to_string(),
+        };
+        let res = self.put(url, serde_json::to_string(&request)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        check(res)?;
+        Ok(())
+    }
+
+    /// Adds and removes labels, leaving the issue's other labels alone.
+    pub fn update_labels(&self, issue_key: &str, add: &[String], remove: &[String]) -> Result<()> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}", issue_key))?;
+        let mut labels: Vec<LabelUpdate> = add.iter()
+            .map(|label| LabelUpdate::Add(label.clone()))
+            .collect();
+        labels.extend(remove.iter().map(|label| LabelUpdate::Remove(label.clone())));
+        let request = UpdateIssueRequest {
+            update: UpdateLabels { labels: labels },
+        };
+        let res = self.put(url, serde_json::to_string(&request)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        check(res)?;
+        Ok(())
+    }
+
+    /// Links the issue to a web page, e.g. the PR that fixed it.
+    pub fn add_remote_link(&self, issue_key: &str, link_url: &str, title: &str) -> Result<()> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/remotelink", issue_key))?;
+        let request = RemoteLink {
+            object: RemoteObject {
+                url: link_url.to_string(),
+                title: title.to_string(),
+            },
+        };
+        let res = self.post(url, serde_json::to_string(&request)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        check(res)?;
+        Ok(())
+    }
+
+    /// The issue's links to web pages, which unlike links between issues
+    /// aren't part of the issue itself.
+    pub fn remote_links(&self, issue_key: &str) -> Result<Vec<Link>> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/remotelink", issue_key))?;
+        let res = self.get(url)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        let res = check(res)?;
+        let links: Vec<RemoteLink> = serde_json::from_slice(&res.body)?;
+        Ok(links.iter().map(Link::from_remote_link).collect())
+    }
+
+    /// Links two issues with a link type like "Blocks", so that `from`
+    /// blocks `to`.
+    pub fn link_issues(&self, link_type: &str, from: &str, to: &str) -> Result<()> {
+        let url = self.base_url.join("rest/api/2/issueLink")?;
+        // confusingly, jira reads the outward description ("blocks") from the
+        // inward issue to the outward one
+        let request = IssueLinkRequest {
+            link_type: LinkTypeName {
+                name: link_type.to_string(),
+            },
+            inwardIssue: IssueKey {
+                key: from.to_string(),
+            },
+            outwardIssue: IssueKey {
+                key: to.to_string(),
+            },
+        };
+        let res = self.post(url, serde_json::to_string(&request)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {} or {}", from, to)).into());
+        }
+        check(res)?;
+        Ok(())
+    }
+
+    /// Uploads files, given as (file name, contents), to the issue and
+    /// returns the new attachments.
+    pub fn attach(&self, issue_key: &str, files: &[(String, Vec<u8>)]) -> Result<Vec<Attachment>> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/attachments", issue_key))?;
+        // without this header jira turns uploads away as cross-site requests
+        let request = Request::new(Method::Post, url)
+            .header("X-Atlassian-Token", "no-check")
+            .multipart("file", files);
+        let res = self.client.send(request)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        let res = check(res)?;
+        let attachments: Vec<AttachmentFields> = serde_json::from_slice(&res.body)?;
+        Ok(attachments
+            .iter()
+            .map(Attachment::from_attachment_fields)
+            .collect())
+    }
+
+    /// Downloads an attachment's contents.
+    pub fn download(&self, attachment: &Attachment) -> Result<Vec<u8>> {
+        let res = self.get(Url::parse(&attachment.content_url)?)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("attachment {}", attachment.filename)).into());
+        }
+        Ok(check(res)?.body)
+    }
+
+    /// The user we're logged in as.
+    pub fn myself(&self) -> Result<UserFields> {
+        let url = self.base_url.join("rest/api/2/myself")?;
+        let res = check(self.get(url)?)?;
+        Ok(serde_json::from_slice(&res.body)?)
+    }
+
+    pub fn worklogs(&self, issue_key: &str) -> Result<Vec<Worklog>> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/worklog", issue_key))?;
+        let res = self.get(url)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        let res = check(res)?;
+        let list: WorklogList = serde_json::from_slice(&res.body)?;
+        Ok(list.worklogs)
+    }
+
+    /// The transitions the issue's workflow allows from its current status.
+    pub fn transitions(&self, issue_key: &str) -> Result<Vec<Transition>> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/transitions", issue_key))?;
+        let res = self.get(url)?;
+        if res.status == 404 {
+            return Err(ErrorKind::NotFound(format!("issue {}", issue_key)).into());
+        }
+        let res = check(res)?;
+        let list: TransitionList = serde_json::from_slice(&res.body)?;
+        Ok(list.transitions)
+    }
+
+    /// Moves the issue to the first of `statuses` its workflow allows and
+    /// returns the name of that status. Workflows differ in what they call
+    /// things, hence the list.
+    pub fn transition_to(&self, issue_key: &str, statuses: &[&str]) -> Result<String> {
+        let transitions = self.transitions(issue_key)?;
+        let transition = statuses
+            .iter()
+            .filter_map(|status| {
+                transitions
+                    .iter()
+                    .find(|t| t.to.name.eq_ignore_ascii_case(status))
+            })
+            .next()
+            .ok_or_else(|| ErrorKind::NoTransition(issue_key.to_string(), statuses.join(" or ")))?;
+
+        let url = self.base_url
+            .join(&format!("rest/api/2/issue/{}/transitions", issue_key))?;
+        let request = TransitionRequest {
+            transition: TransitionId {
+                id: transition.id.clone(),
+            },
+        };
+        check(self.post(url, serde_json::to_string(&request)?)?)?;
+        Ok(transition.to.name.clone())
+    }
+
+    pub fn project_exists(&self, project_key: &str) -> Result<bool> {
+        let url = self.base_url
+            .join(&format!("rest/api/2/project/{}", project_key))?;
+        let res = self.get(url)?;
+        Ok(res.is_success())
+    }
+
+    pub fn user_exists(&self, username: &str) -> Result<bool> {
+        let mut url = self.base_url.join("rest/api/2/user")?;
+        url.query_pairs_mut().append_pair("username", username);
+        let res = self.get(url)?;
+        Ok(res.is_success())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::MockTransport;
+
+    const AUTH: &str = "Ym9iOmh1bnRlcjI="; // bob:hunter2
+    const BASE_URL: &str = "https://jira.example.com/";
+
+    fn jira(transport: &MockTransport) -> Jira<MockTransport> {
+        Jira::with_transport(transport.clone(), AUTH, BASE_URL).unwrap()
+    }
+
+    #[test]
+    fn logs_in_and_sends_the_session_cookie() {
+        let transport = MockTransport::new().on(
+            Method::Get,
+            "/rest/api/2/issue/FOO-1",
+            200,
+            include_str!("../tests/fixtures/issue.json"),
+        );
+        jira(&transport).issue("FOO-1").unwrap();
+
+        let requests = transport.requests();
+        let login = &requests[0];
+        assert_eq!(login.method, Method::Post);
+        assert_eq!(
+            login.text(),
+            "{\"username\":\"bob\",\"password\":\"hunter2\"}"
+        );
+
+        let cookie = requests[1]
+            .headers
+            .iter()
+            .find(|&&(ref name, _)| name == "Cookie")
+            .map(|&(_, ref value)| value.clone());
+        assert_eq!(cookie, Some("JSESSIONID=6E3487971234567896704A9EB4AE501F".to_string()));
+    }
+
+    #[test]
+    fn rejected_login_is_an_authentication_failure() {
+        let transport = MockTransport::default().on(
+            Method::Post,
+            "/rest/auth/1/session",
+            401,
+            "{\"errorMessages\":[\"Login failed\"],\"errors\":{}}",
+        );
+        match Jira::with_transport(transport, AUTH, BASE_URL) {
+            Err(e) => match *e.kind() {
+                ErrorKind::AuthenticationFailed(_) => {}
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("login should have failed"),
+        }
+    }
+
+    #[test]
+    fn query_returns_issues() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/search",
+            200,
+            include_str!("../tests/fixtures/search.json"),
+        );
+        let issues = jira(&transport).query("project in (\"FOO\")").unwrap();
+
+        // jira returns newest first, we display oldest first
+        assert_eq!(issues.get(0).unwrap().key, "FOO-1");
+        assert_eq!(issues.get(1).unwrap().key, "FOO-2");
+        assert!(issues.get(2).is_none());
+
+        let issue = issues.get(0).unwrap();
+        assert_eq!(issue.status, "Open");
+        assert_eq!(issue.assignee, "Foo Robot");
+        assert_eq!(issue.reporter, "Alice");
+        assert_eq!(issue.browse_url, "https://jira.example.com/browse/FOO-1");
+
+        let body = transport.requests()[1].text();
+        assert!(body.contains("\"jql\":\"project in (\\\"FOO\\\")\""));
+    }
+
+    #[test]
+    fn invalid_jql_reports_the_jira_error() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/search",
+            400,
+            include_str!("../tests/fixtures/invalid_jql.json"),
+        );
+        match jira(&transport).query("project in (") {
+            Err(e) => match *e.kind() {
+                ErrorKind::JiraError(400, ref message) => {
+                    assert!(message.contains("Error in the JQL Query"))
+                }
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("query should have failed"),
+        }
+    }
+
+    #[test]
+    fn create_issue_returns_the_created_issue() {
+        let transport = MockTransport::new()
+            .on(
+                Method::Post,
+                "/rest/api/2/issue",
+                201,
+                "{\"id\":\"10001\",\"key\":\"FOO-1\",\"self\":\"https://jira.example.com/rest/api/2/issue/10001\"}",
+            )
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1",
+                200,
+                include_str!("../tests/fixtures/issue.json"),
+            );
+        let new_issue = NewIssue {
+            project: "FOO".to_string(),
+            summary: "The build is broken".to_string(),
+            assignee: "foo-robot".to_string(),
+            labels: vec!["interrupt".to_string()],
+            ..NewIssue::default()
+        };
+        let issue = jira(&transport).create_issue(&new_issue).unwrap();
+        assert_eq!(issue.key, "FOO-1");
+        assert_eq!(issue.summary, "The build is broken");
+
+        let body = transport.requests()[1].text();
+        assert!(body.contains("\"project\":{\"key\":\"FOO\"}"));
+        assert!(body.contains("\"assignee\":{\"name\":\"foo-robot\"}"));
+        assert!(body.contains("\"labels\":[\"interrupt\"]"));
+        assert!(body.contains("\"issuetype\":{\"name\":\"Bug\"}"));
+        assert!(!body.contains("parent"));
+    }
+
+    #[test]
+    fn create_issue_sets_the_parent_and_epic() {
+        let transport = MockTransport::new()
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/createmeta",
+                200,
+                include_str!("../tests/fixtures/createmeta.json"),
+            )
+            .on(Method::Post, "/rest/api/2/issue", 201, "{\"key\":\"FOO-1\"}")
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1",
+                200,
+                include_str!("../tests/fixtures/issue.json"),
+            );
+        let jira = jira(&transport).epic_link_field("customfield_10100");
+
+        let subtask = NewIssue {
+            project: "FOO".to_string(),
+            summary: "Rebuild the agents".to_string(),
+            parent: Some("FOO-7".to_string()),
+            ..NewIssue::default()
+        };
+        jira.create_issue(&subtask).unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&transport.requests()[2].text()).unwrap();
+        assert_eq!(body["fields"]["parent"]["key"], "FOO-7");
+        assert!(body["fields"]["parent"].get("fields").is_none());
+        assert_eq!(body["fields"]["issuetype"]["name"], "Sub-task");
+
+        let in_epic = NewIssue {
+            project: "FOO".to_string(),
+            summary: "Rebuild the agents".to_string(),
+            epic: Some("FOO-100".to_string()),
+            ..NewIssue::default()
+        };
+        jira.create_issue(&in_epic).unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&transport.requests()[4].text()).unwrap();
+        assert_eq!(body["fields"]["customfield_10100"], "FOO-100");
+        assert!(body["fields"].get("parent").is_none());
+    }
+
+    #[test]
+    fn subtasks_get_the_subtask_type_the_project_has() {
+        let meta = include_str!("../tests/fixtures/createmeta.json");
+        let cloud_meta = meta.replace("Sub-task", "Subtask");
+        let transport = MockTransport::new()
+            .on(Method::Get, "/rest/api/2/issue/createmeta", 200, &cloud_meta)
+            .on(Method::Post, "/rest/api/2/issue", 201, "{\"key\":\"FOO-1\"}")
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1",
+                200,
+                include_str!("../tests/fixtures/issue.json"),
+            );
+        let subtask = NewIssue {
+            project: "FOO".to_string(),
+            summary: "Rebuild the agents".to_string(),
+            parent: Some("FOO-7".to_string()),
+            ..NewIssue::default()
+        };
+        jira(&transport).create_issue(&subtask).unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&transport.requests()[2].text()).unwrap();
+        assert_eq!(body["fields"]["issuetype"]["name"], "Subtask");
+
+        let no_subtasks = meta.replace("\"subtask\": true", "\"subtask\": false");
+        let transport =
+            MockTransport::new().on(Method::Get, "/rest/api/2/issue/createmeta", 200, &no_subtasks);
+        match *jira(&transport).create_issue(&subtask).unwrap_err().kind() {
+            ErrorKind::NoSubtaskType(ref project) => assert_eq!(project, "FOO"),
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn create_issue_passes_extra_fields_through() {
+        let transport = MockTransport::new()
+            .on(Method::Post, "/rest/api/2/issue", 201, "{\"key\":\"FOO-1\"}")
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1",
+                200,
+                include_str!("../tests/fixtures/issue.json"),
+            );
+        let mut new_issue = NewIssue {
+            project: "FOO".to_string(),
+            summary: "Disk full".to_string(),
+            ..NewIssue::default()
+        };
+        let priority: serde_json::Value = serde_json::from_str("{\"name\":\"High\"}").unwrap();
+        let task: serde_json::Value = serde_json::from_str("{\"name\":\"Task\"}").unwrap();
+        new_issue.fields.insert("priority".to_string(), priority);
+        new_issue.fields.insert("issuetype".to_string(), task);
+        jira(&transport).create_issue(&new_issue).unwrap();
+
+        let body: serde_json::Value =
+            serde_json::from_str(&transport.requests()[1].text()).unwrap();
+        assert_eq!(body["fields"]["priority"]["name"], "High");
+        assert_eq!(body["fields"]["issuetype"]["name"], "Task");
+        assert_eq!(body["fields"]["summary"], "Disk full");
+    }
+
+    #[test]
+    fn create_issues_reports_each_issue() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/issue/bulk",
+            400,
+            include_str!("../tests/fixtures/bulk_create.json"),
+        );
+        let fields: Vec<serde_json::Value> = (0..3)
+            .map(|i| serde_json::from_str(&format!("{{\"summary\":\"issue {}\"}}", i)).unwrap())
+            .collect();
+        let results = jira(&transport).create_issues(&fields).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                Ok("FOO-5".to_string()),
+                Err("assignee: User 'nobody' does not exist.".to_string()),
+                Ok("FOO-6".to_string()),
+            ]
+        );
+
+        let body: serde_json::Value =
+            serde_json::from_str(&transport.requests()[1].text()).unwrap();
+        assert_eq!(body["issueUpdates"][2]["fields"]["summary"], "issue 2");
+    }
+
+    #[test]
+    fn create_issues_fails_when_the_whole_request_is_rejected() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/issue/bulk",
+            403,
+            "{\"errorMessages\":[\"You can't create issues\"],\"errors\":{}}",
+        );
+        let fields: Vec<serde_json::Value> = vec![serde_json::from_str("{}").unwrap()];
+        match jira(&transport).create_issues(&fields) {
+            Err(e) => match *e.kind() {
+                ErrorKind::JiraError(403, ref message) => {
+                    assert_eq!(message, "You can't create issues")
+                }
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("the import should have failed"),
+        }
+    }
+
+    #[test]
+    fn labels_are_added_and_removed_in_one_update() {
+        let transport = MockTransport::new().on(Method::Put, "/rest/api/2/issue/FOO-1", 204, "");
+        jira(&transport)
+            .update_labels("FOO-1", &["stale".to_string()], &["interrupt".to_string()])
+            .unwrap();
+
+        let request = &transport.requests()[1];
+        assert_eq!(request.method, Method::Put);
+        assert_eq!(
+            request.text(),
+            "{\"update\":{\"labels\":[{\"add\":\"stale\"},{\"remove\":\"interrupt\"}]}}"
+        );
+    }
+
+    #[test]
+    fn assigning_a_missing_issue_is_not_found() {
+        let transport = MockTransport::new();
+        match jira(&transport).assign("FOO-99", "bob") {
+            Err(e) => match *e.kind() {
+                ErrorKind::NotFound(ref what) => assert_eq!(what, "issue FOO-99"),
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("assigning should have failed"),
+        }
+        assert_eq!(transport.requests()[1].text(), "{\"name\":\"bob\"}");
+    }
+
+    #[test]
+    fn create_issue_reports_field_errors() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/issue",
+            400,
+            include_str!("../tests/fixtures/create_error.json"),
+        );
+        let new_issue = NewIssue {
+            project: "FOO".to_string(),
+            summary: "summary".to_string(),
+            assignee: "nobody".to_string(),
+            ..NewIssue::default()
+        };
+        match jira(&transport).create_issue(&new_issue) {
+            Err(e) => match *e.kind() {
+                ErrorKind::JiraError(400, ref message) => {
+                    assert!(message.contains("assignee: User 'nobody' does not exist."))
+                }
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("create should have failed"),
+        }
+    }
+
+    #[test]
+    fn issue_returns_the_issue() {
+        let transport = MockTransport::new().on(
+            Method::Get,
+            "/rest/api/2/issue/FOO-1",
+            200,
+            include_str!("../tests/fixtures/issue.json"),
+        );
+        let issue = jira(&transport).issue("FOO-1").unwrap();
+        assert_eq!(issue.key, "FOO-1");
+        assert_eq!(issue.labels, vec!["interrupt".to_string()]);
+
+        let links: Vec<String> = issue.links.iter().map(|link| link.to_string()).collect();
+        assert_eq!(
+            links,
+            vec!["blocks FOO-2 (Release 1.2)", "is duplicated by FOO-3 (CI is red)"]
+        );
+    }
+
+    #[test]
+    fn attach_uploads_files_without_the_xsrf_check() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/issue/FOO-1/attachments",
+            200,
+            "[{\"id\":\"10200\",\"filename\":\"build.log\",\"size\":6,\
+             \"content\":\"https://jira.example.com/secure/attachment/10200/build.log\"}]",
+        );
+        let files = vec![("build.log".to_string(), b"failed".to_vec())];
+        let attachments = jira(&transport).attach("FOO-1", &files).unwrap();
+        assert_eq!(attachments[0].filename, "build.log");
+        assert_eq!(attachments[0].size, 6);
+
+        let upload = &transport.requests()[1];
+        assert!(upload
+            .headers
+            .contains(&("X-Atlassian-Token".to_string(), "no-check".to_string())));
+        assert!(upload.text().contains("filename=\"build.log\""));
+    }
+
+    #[test]
+    fn remote_links_are_posted_and_read_back() {
+        let transport = MockTransport::new()
+            .on(Method::Post, "/rest/api/2/issue/FOO-1/remotelink", 201, "{\"id\":10000}")
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1/remotelink",
+                200,
+                "[{\"id\":10000,\"object\":{\"url\":\"https://git.example.com/pr/7\",\"title\":\"Fix the build\"}}]",
+            );
+        let jira = jira(&transport);
+        jira.add_remote_link("FOO-1", "https://git.example.com/pr/7", "Fix the build")
+            .unwrap();
+        assert_eq!(
+            transport.requests()[1].text(),
+            "{\"object\":{\"url\":\"https://git.example.com/pr/7\",\"title\":\"Fix the build\"}}"
+        );
+
+        let links = jira.remote_links("FOO-1").unwrap();
+        assert_eq!(links[0].to_string(), "links to https://git.example.com/pr/7 (Fix the build)");
+    }
+
+    fn no_delay() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay_ms: 0,
+            max_delay_ms: 0,
+        }
+    }
+
+    #[test]
+    fn retries_gets_on_server_errors() {
+        let transport = MockTransport::new()
+            .on(Method::Get, "/rest/api/2/issue/FOO-1", 503, "")
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1",
+                200,
+                include_str!("../tests/fixtures/issue.json"),
+            );
+        let issue = jira(&transport)
+            .retry_policy(no_delay())
+            .issue("FOO-1")
+            .unwrap();
+        assert_eq!(issue.key, "FOO-1");
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[test]
+    fn retries_searches_when_rate_limited() {
+        let transport = MockTransport::new()
+            .respond(
+                Method::Post,
+                "/rest/api/2/search",
+                Response {
+                    status: 429,
+                    headers: vec![("Retry-After".to_string(), "0".to_string())],
+                    body: Vec::new(),
+                },
+            )
+            .on(
+                Method::Post,
+                "/rest/api/2/search",
+                200,
+                include_str!("../tests/fixtures/search.json"),
+            );
+        let issues = jira(&transport)
+            .retry_policy(no_delay())
+            .query("project = FOO")
+            .unwrap();
+        assert_eq!(issues.get(0).unwrap().key, "FOO-1");
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let transport = MockTransport::new().on(Method::Get, "/rest/api/2/issue/FOO-1", 502, "");
+        match jira(&transport).retry_policy(no_delay()).issue("FOO-1") {
+            Err(e) => match *e.kind() {
+                ErrorKind::JiraError(502, _) => {}
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("issue should have failed"),
+        }
+        // the login plus one attempt and two retries
+        assert_eq!(transport.requests().len(), 4);
+    }
+
+    #[test]
+    fn does_not_retry_creating_issues() {
+        let transport = MockTransport::new().on(Method::Post, "/rest/api/2/issue", 503, "");
+        let result = jira(&transport)
+            .retry_policy(no_delay())
+            .create_issue(&NewIssue::default());
+        assert!(result.is_err());
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[test]
+    fn backoff_doubles_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay_ms: 100,
+            max_delay_ms: 300,
+        };
+        assert_eq!(policy.delay(0, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(1, None), Duration::from_millis(200));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(300));
+        assert_eq!(policy.delay(0, Some(2)), Duration::from_millis(300));
+        assert_eq!(policy.delay(0, Some(0)), Duration::from_millis(0));
+    }
+
+    #[test]
+    fn add_worklog_sends_seconds_and_comment() {
+        let transport = MockTransport::new().on(
+            Method::Post,
+            "/rest/api/2/issue/FOO-1/worklog",
+            201,
+            "{}",
+        );
+        jira(&transport)
+            .add_worklog("FOO-1", 5400, Some("rebooted it"), None)
+            .unwrap();
+
+        let body: serde_json::Value =
+            serde_json::from_str(&transport.requests()[1].text()).unwrap();
+        assert_eq!(body["timeSpentSeconds"], 5400);
+        assert_eq!(body["comment"], "rebooted it");
+        assert!(DateTime::parse_from_str(body["started"].as_str().unwrap(), JIRA_TIME_FORMAT).is_ok());
+    }
+
+    #[test]
+    fn query_worklogs_only_fetches_worklogs_the_search_left_out() {
+        // jira cloud only identifies users by accountId
+        let worklog = "{\"author\":{\"accountId\":\"5b10a\",\"displayName\":\"Bob\"},\
+                       \"started\":\"2017-05-01T09:00:00.000+0000\",\"timeSpentSeconds\":3600}";
+        let search = format!(
+            "{{\"total\":2,\"issues\":[\
+             {{\"key\":\"FOO-2\",\"self\":\"https://jira.example.com/rest/api/2/issue/2\",\
+               \"fields\":{{\"summary\":\"two\",\"labels\":[],\
+               \"worklog\":{{\"total\":1,\"worklogs\":[{0}]}}}}}},\
+             {{\"key\":\"FOO-1\",\"self\":\"https://jira.example.com/rest/api/2/issue/1\",\
+               \"fields\":{{\"summary\":\"one\",\"labels\":[],\
+               \"worklog\":{{\"total\":2,\"worklogs\":[{0}]}}}}}}]}}",
+            worklog
+        );
+        let transport = MockTransport::new()
+            .on(Method::Get, "/rest/api/2/myself", 200, "{\"accountId\":\"5b10a\"}")
+            .on(Method::Post, "/rest/api/2/search", 200, &search)
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1/worklog",
+                200,
+                &format!("{{\"total\":2,\"worklogs\":[{0},{0}]}}", worklog),
+            );
+        let jira = jira(&transport);
+        let me = jira.myself().unwrap();
+        let issues = jira.query_worklogs("worklogAuthor = currentUser()").unwrap();
+
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].0.key, "FOO-1");
+        assert_eq!(issues[0].1.len(), 2);
+        assert_eq!(issues[1].0.key, "FOO-2");
+        assert_eq!(issues[1].1.len(), 1);
+        assert!(issues.iter().all(|&(_, ref worklogs)| worklogs.iter().all(|w| w.author.is(&me))));
+
+        let requests = transport.requests();
+        assert!(requests[2].text().contains("\"worklog\""));
+        let worklog_requests: Vec<&str> = requests
+            .iter()
+            .map(|request| request.url.path())
+            .filter(|path| path.ends_with("/worklog"))
+            .collect();
+        assert_eq!(worklog_requests, vec!["/rest/api/2/issue/FOO-1/worklog"]);
+    }
+
+    #[test]
+    fn transition_to_picks_the_first_available_status() {
+        let transport = MockTransport::new()
+            .on(
+                Method::Get,
+                "/rest/api/2/issue/FOO-1/transitions",
+                200,
+                "{\"transitions\":[{\"id\":\"21\",\"name\":\"Stop Progress\",\"to\":{\"name\":\"Open\"}},\
+                 {\"id\":\"31\",\"name\":\"Resolve Issue\",\"to\":{\"name\":\"Resolved\"}}]}",
+            )
+            .on(Method::Post, "/rest/api/2/issue/FOO-1/transitions", 204, "");
+
+        let status = jira(&transport)
+            .transition_to("FOO-1", &["Closed", "Resolved"])
+            .unwrap();
+        assert_eq!(status, "Resolved");
+        assert_eq!(
+            transport.requests()[2].text(),
+            "{\"transition\":{\"id\":\"31\"}}"
+        );
+
+        match jira(&transport).transition_to("FOO-1", &["In Progress"]) {
+            Err(e) => match *e.kind() {
+                ErrorKind::NoTransition(..) => {}
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("there is no transition to In Progress"),
+        }
+    }
+
+    #[test]
+    fn missing_issue_is_not_found() {
+        let transport = MockTransport::new();
+        match jira(&transport).issue("FOO-404") {
+            Err(e) => match *e.kind() {
+                ErrorKind::NotFound(ref what) => assert_eq!(what, "issue FOO-404"),
+                ref other => panic!("unexpected error: {}", other),
+            },
+            Ok(_) => panic!("issue should not have been found"),
+        }
+    }
 }
@@ -1,4 +1,8 @@
 use serde::{Deserialize, Deserializer};
+use serde_json;
+use std::collections::BTreeMap;
+
+use issue::NewIssue;
 
 #[derive(Serialize, Debug, PartialEq)]
 pub struct AuthRequest {
@@ -18,10 +22,32 @@ pub struct Session {
 }
 
 #[allow(non_snake_case)]
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct ErrorResponse {
+    #[serde(default)] pub errorMessages: Vec<String>,
+    #[serde(default)] pub errors: BTreeMap<String, String>,
+}
+
+impl ErrorResponse {
+    pub fn is_empty(&self) -> bool {
+        self.errorMessages.is_empty() && self.errors.is_empty()
+    }
+
+    pub fn message(&self) -> String {
+        let mut messages = self.errorMessages.clone();
+        for (field, message) in &self.errors {
+            messages.push(format!("{}: {}", field, message));
+        }
+        messages.join("; ")
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct JQLQuery {
     jql: String,
     fields: Vec<String>,
+    startAt: usize,
     maxResults: u16,
 }
 
@@ -36,9 +62,24 @@ impl JQLQuery {
                 "reporter".to_string(),
                 "labels".to_string(),
             ],
+            startAt: 0,
             maxResults: 200,
         }
     }
+
+    /// The same query, also fetching `field` for every issue.
+    pub fn with_field(mut self, field: &str) -> JQLQuery {
+        self.fields.push(field.to_string());
+        self
+    }
+
+    /// The same query, for the page of results starting at `start_at`.
+    pub fn starting_at(self, start_at: usize) -> JQLQuery {
+        JQLQuery {
+            startAt: start_at,
+            ..self
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -58,9 +99,11 @@ pub struct IssueResponse {
     #[serde(rename = "self")] pub self_url: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
 pub struct IssueResponseList {
     pub issues: Vec<IssueResponse>,
+    /// How many issues match the query, across all pages.
+    #[serde(default)] pub total: usize,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -73,6 +116,11 @@ pub struct IssueFields {
     #[serde(default = "default_issuetype")] pub issuetype: IssueTypeFields,
     #[serde(skip_serializing)] pub reporter: Option<UserFields>,
     #[serde(skip_serializing)] pub status: Option<Status>,
+    #[serde(default, skip_serializing)] pub issuelinks: Vec<IssueLink>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub parent: Option<LinkedIssue>,
+    #[serde(default, skip_serializing)] pub subtasks: Vec<LinkedIssue>,
+    #[serde(default, skip_serializing)] pub attachment: Vec<AttachmentFields>,
+    #[serde(default, skip_serializing)] pub worklog: Option<WorklogList>,
 }
 
 fn nullable_user_fields<'de, D>(deserializer: D) -> Result<UserFields, D::Error>
@@ -88,6 +136,8 @@ fn nullable_user_fields<'de, D>(deserializer: D) -> Result<UserFields, D::Error>
 fn default_user() -> UserFields {
     UserFields {
         name: "<unknown>".to_string(),
+        key: None,
+        accountId: None,
         displayName: None,
     }
 }
@@ -121,7 +171,10 @@ fn default_issuetype() -> IssueTypeFields {
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct UserFields {
-    pub name: String,
+    // jira cloud only sends the accountId, server only the name and key
+    #[serde(default)] pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub accountId: Option<String>,
     #[serde(skip_serializing)] displayName: Option<String>,
 }
 
@@ -132,6 +185,256 @@ impl UserFields {
             None => "Unknown".to_string(),
         }
     }
+
+    /// Whether both are the same user, by the most specific id both have.
+    pub fn is(&self, other: &UserFields) -> bool {
+        match (&self.accountId, &other.accountId, &self.key, &other.key) {
+            (&Some(ref a), &Some(ref b), _, _) => a == b,
+            (_, _, &Some(ref a), &Some(ref b)) => a == b,
+            _ => !self.name.is_empty() && self.name == other.name,
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Debug, PartialEq)]
+pub struct WorklogRequest {
+    pub timeSpentSeconds: u64,
+    pub started: String,
+    #[serde(skip_serializing_if = "Option::is_none")] pub comment: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Worklog {
+    #[serde(default = "default_user", deserialize_with = "nullable_user_fields")] pub author: UserFields,
+    pub started: String,
+    pub timeSpentSeconds: u64,
+    #[serde(default)] pub comment: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct WorklogList {
+    pub worklogs: Vec<Worklog>,
+    /// How many worklogs the issue has, a search only sends the first few.
+    #[serde(default)] pub total: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CommentRequest {
+    pub body: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Transition {
+    pub id: String,
+    pub name: String,
+    pub to: Status,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct TransitionList {
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TransitionId {
+    pub id: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TransitionRequest {
+    pub transition: TransitionId,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct IssueLinkType {
+    pub name: String,
+    #[serde(default)] pub inward: String,
+    #[serde(default)] pub outward: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct LinkedIssueFields {
+    #[serde(default)] pub summary: String,
+    #[serde(default)] pub status: Option<Status>,
+}
+
+/// Another issue as it's embedded in an issue, e.g. as a link, parent or
+/// subtask. Only the key is sent when creating an issue.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct LinkedIssue {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub fields: Option<LinkedIssueFields>,
+}
+
+/// One of an issue's links, only one of the two issues is set: the other end
+/// is the issue the link was read from.
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct IssueLink {
+    #[serde(rename = "type")] pub link_type: IssueLinkType,
+    pub inwardIssue: Option<LinkedIssue>,
+    pub outwardIssue: Option<LinkedIssue>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AssignRequest {
+    pub name: String,
+}
+
+/// One change to an issue's labels, serialized as `{"add": "label"}`.
+#[derive(Serialize, Debug, PartialEq)]
+pub enum LabelUpdate {
+    #[serde(rename = "add")] Add(String),
+    #[serde(rename = "remove")] Remove(String),
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UpdateLabels {
+    pub labels: Vec<LabelUpdate>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UpdateIssueRequest {
+    pub update: UpdateLabels,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct IssueKey {
+    pub key: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LinkTypeName {
+    pub name: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Debug, PartialEq)]
+pub struct IssueLinkRequest {
+    #[serde(rename = "type")] pub link_type: LinkTypeName,
+    pub inwardIssue: IssueKey,
+    pub outwardIssue: IssueKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RemoteObject {
+    pub url: String,
+    #[serde(default)] pub title: String,
+}
+
+/// A link to something outside of jira, e.g. a pull request. Used both for
+/// creating and for reading them.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RemoteLink {
+    pub object: RemoteObject,
+}
+
+/// A file attached to an issue, `content` is where to download it from.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct AttachmentFields {
+    pub id: String,
+    pub filename: String,
+    #[serde(default)] pub size: u64,
+    #[serde(default)] pub created: String,
+    #[serde(default = "default_user", deserialize_with = "nullable_user_fields")] pub author: UserFields,
+    pub content: String,
+}
+
+/// What can be set when creating issues, per project and issue type.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct CreateMeta {
+    pub projects: Vec<ProjectMeta>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ProjectMeta {
+    pub key: String,
+    #[serde(default)] pub issuetypes: Vec<IssueTypeMeta>,
+}
+
+impl ProjectMeta {
+    /// The type for a new issue that doesn't name one: the type `ob new` has
+    /// always used if the project has it, otherwise the project's first type
+    /// of the right kind, since subtasks need a subtask type.
+    pub fn default_issuetype(&self, subtask: bool) -> Option<&IssueTypeMeta> {
+        let usual = if subtask { "Sub-task" } else { DEFAULT_ISSUE_TYPE };
+        self.issuetypes
+            .iter()
+            .find(|issuetype| issuetype.subtask == subtask && issuetype.name == usual)
+            .or_else(|| self.issuetypes.iter().find(|issuetype| issuetype.subtask == subtask))
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct IssueTypeMeta {
+    pub name: String,
+    #[serde(default)] pub subtask: bool,
+    /// Keyed by field id, e.g. `summary` or `customfield_10200`.
+    #[serde(default)] pub fields: BTreeMap<String, FieldMeta>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct FieldMeta {
+    pub name: String,
+    #[serde(default)] pub required: bool,
+    #[serde(default)] pub hasDefaultValue: bool,
+    pub schema: FieldSchema,
+    #[serde(default)] pub allowedValues: Vec<AllowedValue>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct FieldSchema {
+    #[serde(rename = "type")] pub field_type: String,
+    /// The type of an array's items.
+    #[serde(default)] pub items: Option<String>,
+}
+
+/// One of the values a select-like field takes, which of the names is set
+/// depends on the kind of field.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct AllowedValue {
+    #[serde(default)] pub value: Option<String>,
+    #[serde(default)] pub name: Option<String>,
+    #[serde(default)] pub key: Option<String>,
+}
+
+impl AllowedValue {
+    pub fn label(&self) -> Option<&str> {
+        self.value
+            .as_ref()
+            .or(self.name.as_ref())
+            .or(self.key.as_ref())
+            .map(|label| label.as_str())
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BulkIssueUpdate {
+    pub fields: serde_json::Value,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BulkCreateRequest {
+    pub issueUpdates: Vec<BulkIssueUpdate>,
+}
+
+/// `issues` holds the created issues in request order, skipping the ones
+/// listed in `errors`.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct BulkCreateResponse {
+    #[serde(default)] pub issues: Vec<CreateIssueResponse>,
+    #[serde(default)] pub errors: Vec<BulkCreateError>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct BulkCreateError {
+    pub failedElementNumber: usize,
+    #[serde(default)] pub elementErrors: ErrorResponse,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -149,31 +452,38 @@ pub struct IssueTypeFields {
     pub name: String,
 }
 
+/// The issue type of new issues that aren't subtasks.
+pub const DEFAULT_ISSUE_TYPE: &str = "Bug";
+
 impl CreateIssueRequest {
-    pub fn new(
-        project_key: &str,
-        summary: &str,
-        description: &str,
-        assignee: &str,
-        labels: &Vec<String>,
-    ) -> Self {
+    pub fn new(new_issue: &NewIssue, issuetype: &str) -> Self {
         CreateIssueRequest {
             fields: IssueFields {
                 assignee: UserFields {
-                    name: assignee.to_string(),
+                    name: new_issue.assignee.clone(),
+                    key: None,
+                    accountId: None,
                     displayName: None,
                 },
-                description: description.to_string(),
+                description: new_issue.description.clone(),
                 issuetype: IssueTypeFields {
-                    name: "Bug".to_string(),
+                    name: issuetype.to_string(),
                 },
-                labels: labels.clone(),
+                labels: new_issue.labels.clone(),
                 project: ProjectFields {
-                    key: project_key.to_string(),
+                    key: new_issue.project.clone(),
                 },
                 reporter: None,
                 status: None,
-                summary: summary.to_string(),
+                issuelinks: Vec::new(),
+                parent: new_issue.parent.as_ref().map(|key| LinkedIssue {
+                    key: key.clone(),
+                    fields: None,
+                }),
+                subtasks: Vec::new(),
+                attachment: Vec::new(),
+                worklog: None,
+                summary: new_issue.summary.clone(),
             },
         }
     }
@@ -0,0 +1,120 @@
+use std::fmt;
+
+/// A value on the right hand side of a JQL clause.
+///
+/// Anything that comes from user input or the config file should be turned
+/// into a `Value` before being spliced into a query so that it is quoted and
+/// escaped correctly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A string literal, always rendered quoted and escaped
+    Str(String),
+    /// The `EMPTY` keyword, which matches fields that have no value
+    Empty,
+}
+
+impl Value {
+    pub fn string<S: Into<String>>(value: S) -> Value {
+        Value::Str(value.into())
+    }
+
+    /// Interprets a user name from the config file. Jira displays unassigned
+    /// issues as `Unassigned`, but JQL needs the `EMPTY` keyword to match them.
+    pub fn user(name: &str) -> Value {
+        let name = name.trim();
+        match name.to_lowercase().as_str() {
+            "unassigned" | "empty" | "null" => Value::Empty,
+            _ => Value::string(name),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Str(ref value) => write!(f, "{}", quote(value)),
+            Value::Empty => write!(f, "EMPTY"),
+        }
+    }
+}
+
+/// A parenthesized list of values for use with `in` and `not in`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct List(pub Vec<Value>);
+
+impl List {
+    pub fn strings<S: AsRef<str>>(values: &[S]) -> List {
+        List(values.iter().map(|v| Value::string(v.as_ref())).collect())
+    }
+
+    pub fn users<S: AsRef<str>>(values: &[S]) -> List {
+        List(values.iter().map(|v| Value::user(v.as_ref())).collect())
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values: Vec<String> = self.0.iter().map(|v| v.to_string()).collect();
+        write!(f, "({})", values.join(", "))
+    }
+}
+
+/// Quotes a string as a JQL string literal, escaping anything that would
+/// otherwise terminate the literal early.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_plain_strings() {
+        assert_eq!(quote("FOO"), "\"FOO\"");
+    }
+
+    #[test]
+    fn quotes_reserved_words_and_punctuation() {
+        assert_eq!(quote("and"), "\"and\"");
+        assert_eq!(quote("john.doe"), "\"john.doe\"");
+        assert_eq!(quote("foo-robot"), "\"foo-robot\"");
+    }
+
+    #[test]
+    fn escapes_quotes_and_backslashes() {
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote("a\\b"), "\"a\\\\b\"");
+        assert_eq!(quote("a\nb"), "\"a\\nb\"");
+    }
+
+    #[test]
+    fn unassigned_users_become_empty() {
+        assert_eq!(Value::user("Unassigned"), Value::Empty);
+        assert_eq!(Value::user("EMPTY"), Value::Empty);
+        assert_eq!(Value::user(" unassigned "), Value::Empty);
+        assert_eq!(Value::user("bob"), Value::string("bob"));
+    }
+
+    #[test]
+    fn renders_lists() {
+        let users = List::users(&["Unassigned", "foo-robot"]);
+        assert_eq!(users.to_string(), "(EMPTY, \"foo-robot\")");
+
+        let projects = List::strings(&["FOO", "BAR"]);
+        assert_eq!(projects.to_string(), "(\"FOO\", \"BAR\")");
+    }
+}
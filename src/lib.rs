@@ -0,0 +1,63 @@
+//! The library behind the `ob` interrupt helper.
+//!
+//! Everything the command line tool does is built from the pieces exported
+//! here, so other tools can load the same config, talk to Jira with the same
+//! client and render issues the same way.
+//!
+//! ```no_run
+//! extern crate oh_bother;
+//!
+//! use std::path::Path;
+//! use oh_bother::{Config, Jira};
+//!
+//! # fn run() -> oh_bother::Result<()> {
+//! let config = Config::new(Path::new("/home/me/.ob.yml"), None, &[])?;
+//! let jira = Jira::from_config(&config)?;
+//! let issues = jira.query(&format!("project in {}", config.projects()))?;
+//! println!("{}", issues);
+//! # Ok(())
+//! # }
+//! # fn main() { run().unwrap(); }
+//! ```
+
+#![recursion_limit = "1024"] // error chain recursion can be deep
+
+extern crate base64;
+extern crate chrono;
+extern crate csv;
+extern crate eprompt;
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+extern crate prettytable;
+extern crate reqwest;
+extern crate rpassword;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_path_to_error;
+extern crate serde_yaml;
+extern crate url;
+
+pub mod bulk;
+pub mod cache;
+pub mod client;
+pub mod config;
+pub mod duration;
+pub mod error;
+pub mod git;
+pub mod import;
+pub mod issue;
+pub mod issue_file;
+pub mod jira;
+pub mod jira_data;
+pub mod jql;
+pub mod state;
+pub mod watch;
+
+pub use config::Config;
+pub use error::{Error, ErrorKind, Result};
+pub use issue::{Issue, IssueVec};
+pub use jira::Jira;
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+
+use oh_bother::error::Result;
+
+/// Writes log records to stderr at the chosen verbosity and, when asked for,
+/// everything down to trace level into a file that can be attached to a bug
+/// report.
+struct Logger {
+    level: LevelFilter,
+    trace_file: Option<Mutex<File>>,
+}
+
+// the http stack logs plenty on its own, only its warnings are interesting
+fn is_ours(target: &str) -> bool {
+    target.starts_with("oh_bother") || target.starts_with("ob")
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn
+            || (is_ours(metadata.target())
+                && (metadata.level() <= self.level || self.trace_file.is_some()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if record.level() <= self.level || record.level() <= Level::Warn {
+            eprintln!(
+                "{}: {}",
+                record.level().to_string().to_lowercase(),
+                record.args()
+            );
+        }
+
+        if let Some(ref file) = self.trace_file {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let mut file = file.lock().unwrap();
+            // a failing trace file shouldn't take the command down with it
+            let _ = writeln!(
+                file,
+                "[{}.{:03} {} {}] {}",
+                now.as_secs(),
+                now.subsec_nanos() / 1_000_000,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref file) = self.trace_file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Installs the logger. `verbosity` is the number of `-v` flags: warnings
+/// only by default, then debug (one line per request) and trace (full
+/// requests and responses).
+pub fn init(verbosity: u64, trace_file: Option<&Path>) -> Result<()> {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    let trace_file = match trace_file {
+        Some(path) => Some(Mutex::new(File::create(path)?)),
+        None => None,
+    };
+
+    log::set_max_level(if trace_file.is_some() {
+        LevelFilter::Trace
+    } else {
+        level
+    });
+    // only fails if a logger is already installed, which would be a bug
+    let _ = log::set_boxed_logger(Box::new(Logger {
+        level: level,
+        trace_file: trace_file,
+    }));
+    Ok(())
+}
@@ -1,36 +1,44 @@
-#![recursion_limit = "1024"] // error chain recursion can be deep
-
-extern crate base64;
+extern crate chrono;
 #[macro_use]
 extern crate clap;
 extern crate eprompt;
 #[macro_use]
-extern crate error_chain;
-extern crate hyper;
+extern crate log;
+extern crate oh_bother;
 extern crate prettytable;
-extern crate rpassword;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde;
 extern crate serde_json;
-extern crate url;
-extern crate yaml_rust;
 
+use std::cmp;
 use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
 use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
+use chrono::{DateTime, Local};
 use clap::{App, Arg, ArgMatches};
 use eprompt::Prompt;
+use prettytable::cell::Cell;
+use prettytable::row::Row;
 
-use config::Config;
-use jira::Jira;
+use oh_bother::{bulk, duration, error, git, import, issue_file, jql, watch};
+use oh_bother::error::ErrorKind;
+use oh_bother::bulk::Operation;
+use oh_bother::config::Config;
+use oh_bother::issue::NewIssue;
+use oh_bother::issue_file::{Format, IssueFile};
+use oh_bother::jira::{Jira, JIRA_TIME_FORMAT};
+use oh_bother::state::Timer;
 
-mod config;
-mod issue;
-mod jira;
-mod jira_data;
+mod logging;
 mod util;
-mod error;
+
+use util::Session;
 
 fn main() {
     let default_config_path = env::home_dir().unwrap().join(".ob.yml");
@@ -45,169 +53,1188 @@ fn main() {
                 .long("config")
                 .global(true),
         )
+        .arg(
+            Arg::with_name("profile")
+                .help("selects a named profile from the config file")
+                .takes_value(true)
+                .short("P")
+                .long("profile")
+                .env("OB_PROFILE")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("set")
+                .help("overrides a config setting, e.g. new_issue_defaults.project_key=INFRA")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .short("s")
+                .long("set")
+                .global(true),
+        )
         .get_matches();
 
+    let mut verbosity = global_count(&matches, "verbose");
+    if global_flag(&matches, "debug") && verbosity == 0 {
+        verbosity = 1;
+    }
+    let trace_file = global_value(&matches, "trace_file");
+    if let Err(why) = logging::init(verbosity, trace_file.map(Path::new)) {
+        eprintln!("error: could not open the trace file: {}", why);
+        process::exit(EXIT_FAILURE);
+    }
+
+    if let Err(why) = run(&matches) {
+        let code = exit_code(&why);
+        eprintln!("error: {}", why);
+        if code == EXIT_CONFIG {
+            eprintln!("Maybe run 'setup' or 'config validate'?");
+        }
+        process::exit(code);
+    }
+}
+
+// exit codes, so that scripts can tell failures apart without parsing output
+const EXIT_FAILURE: i32 = 1;
+const EXIT_CONFIG: i32 = 3;
+const EXIT_AUTH: i32 = 4;
+const EXIT_NOT_FOUND: i32 = 5;
+const EXIT_NETWORK: i32 = 6;
+const EXIT_EMPTY: i32 = 7;
+
+fn exit_code(err: &error::Error) -> i32 {
+    match *err.kind() {
+        ErrorKind::InvalidConfig
+        | ErrorKind::MissingConfig(_)
+        | ErrorKind::InvalidConfigValue(..)
+        | ErrorKind::InvalidOverride(_)
+        | ErrorKind::UnknownProfile(_)
+        | ErrorKind::UnsupportedConfigVersion(_)
+        | ErrorKind::ValidationFailed
+        | ErrorKind::YamlError(_) => EXIT_CONFIG,
+        ErrorKind::AuthenticationFailed(_)
+        | ErrorKind::InvalidCredentials(_)
+        | ErrorKind::JiraError(401, _)
+        | ErrorKind::JiraError(403, _) => EXIT_AUTH,
+        ErrorKind::NotFound(_) | ErrorKind::JiraError(404, _) => EXIT_NOT_FOUND,
+        ErrorKind::RequestError(_) => EXIT_NETWORK,
+        ErrorKind::EmptyResult(_) => EXIT_EMPTY,
+        _ => EXIT_FAILURE,
+    }
+}
+
+// global flags can be given before or after the subcommand
+fn global_flag(matches: &ArgMatches, name: &str) -> bool {
+    matches.is_present(name) || match matches.subcommand() {
+        (_, Some(subcmd)) => subcmd.is_present(name),
+        _ => false,
+    }
+}
+
+// clap copies global args down into the subcommand, so take the larger count
+// rather than adding them up
+fn global_count(matches: &ArgMatches, name: &str) -> u64 {
+    let nested = match matches.subcommand() {
+        (_, Some(subcmd)) => subcmd.occurrences_of(name),
+        _ => 0,
+    };
+    cmp::max(matches.occurrences_of(name), nested)
+}
+
+fn global_value<'a>(matches: &'a ArgMatches<'a>, name: &str) -> Option<&'a str> {
+    matches.value_of(name).or_else(|| match matches.subcommand() {
+        (_, Some(subcmd)) => subcmd.value_of(name),
+        _ => None,
+    })
+}
+
+// like clap's value_t!, but a bad value is an error rather than exiting from
+// the middle of a command
+fn number_value<T: FromStr>(matches: &ArgMatches, name: &str) -> error::Result<Option<T>> {
+    match matches.value_of(name) {
+        Some(raw) => raw
+            .parse()
+            .map(Some)
+            .map_err(|_| ErrorKind::InvalidNumber(name.to_string(), raw.to_string()).into()),
+        None => Ok(None),
+    }
+}
+
+fn subcommand<'a>(matches: &'a ArgMatches<'a>, name: &str) -> error::Result<&'a ArgMatches<'a>> {
+    matches
+        .subcommand_matches(name)
+        .ok_or_else(|| ErrorKind::Unexpected(format!("missing arguments for {}", name)).into())
+}
+
+fn run(matches: &ArgMatches) -> error::Result<()> {
     let config_file = matches.value_of("config").unwrap();
     let config_path = Path::new(config_file);
-
-    let debug = matches.is_present("debug");
+    let profile = matches.value_of("profile");
+    let overrides = matches.values_of_lossy("set").unwrap_or_default();
 
     if matches.is_present("setup") {
-        match Config::create(&config_path) {
-            Err(why) => {
-                println!("There was an error creating the config.");
-                util::exit(&format!(
-                    "couldn't create config file {}: {}",
-                    config_path.display(),
-                    why
-                ))
-            }
-            Ok(_) => {}
-        }
+        Config::create(&config_path, profile)?;
         println!(
             "Please edit {} to include your desired configuration",
             config_path.display()
         );
-    } else {
-        let config = match Config::new(&config_path) {
-            Err(why) => {
-                println!("There was an error loading the config. Maybe run 'setup'?");
-                util::exit(&format!(
-                    "couldn't open config file {}: {}",
-                    config_path.display(),
-                    why
-                ))
-            }
-            Ok(config) => config,
-        };
+        return Ok(());
+    }
 
-        let jira = match Jira::new(config.auth.as_str(), config.jira_url.as_str(), debug) {
-            Err(why) => util::exit(&format!("couldn't construct client: {}", why)),
-            Ok(jira) => jira,
-        };
+    // doesn't need a config, the hook only looks at the branch name
+    if let Some(subcmd) = matches.subcommand_matches("hook") {
+        if let Some(install) = subcmd.subcommand_matches("install") {
+            let path = git::install_hook(install.is_present("force"))?;
+            println!("Installed {}", path.display());
+        }
+        return Ok(());
+    }
+
+    // a missing ~/.ob.yml is fine when /etc/ob.yml has everything, but a
+    // config that was asked for by name has to be there
+    if global_count(matches, "config") > 0 && !config_path.exists() {
+        return Err(ErrorKind::MissingConfig(config_path.display().to_string()).into());
+    }
 
-        match matches.subcommand_name() {
-            Some("issue") => issue(&config, &jira, &matches),
-            Some("list") => list(&config, &jira, &matches),
-            Some("current") => current(&config, &jira, &matches),
-            Some("next") => next(&config, &jira, &matches),
-            Some("start") => println!("start not implemented"),
-            Some("stop") => println!("stop not implemented"),
-            Some("close") => println!("close not implemented"),
-            Some("new") => new(&config, &jira, &matches, debug),
-            Some("jql") => jql(&config, &jira, &matches),
-            _ => util::exit("unknown command"), // shouldn't really ever get here
+    if let Some(subcmd) = matches.subcommand_matches("config") {
+        if subcmd.is_present("validate") {
+            validate_config(&config_path, profile, &overrides)?;
         }
+        if subcmd.is_present("migrate") && !Config::migrate_file(&config_path)? {
+            println!("{} is already up to date", config_path.display());
+        }
+        return Ok(());
+    }
+
+    let config = Config::new(&config_path, profile, &overrides)?;
+    let session = Session::new(
+        config,
+        global_flag(matches, "offline"),
+        global_flag(matches, "refresh"),
+    )?;
+
+    match matches.subcommand_name() {
+        Some("issue") => issue(&session, matches),
+        Some("list") => list(&session, matches),
+        Some("current") => current(&session, matches),
+        Some("next") => next(&session, matches),
+        Some("start") => start(&session, matches),
+        Some("stop") => stop(&session, matches, "stop", STOP_STATUSES),
+        Some("close") => stop(&session, matches, "close", CLOSE_STATUSES),
+        Some("status") => status(&session),
+        Some("comment") => comment(&session, matches),
+        Some("label") => label(&session, matches),
+        Some("labels") => labels(&session, matches),
+        Some("switch") => switch(&session, matches),
+        Some("branch") => branch(&session, matches),
+        Some("link") => link(&session, matches),
+        Some("attach") => attach(&session, matches),
+        Some("attachments") => attachments(&session, matches),
+        Some("new") => new(&session, matches),
+        Some("import") => import(&session, matches),
+        Some("bulk") => bulk(&session, matches),
+        Some("jql") => jql(&session, matches),
+        Some("watch") => watch(&session, matches),
+        Some("log") => log_work(&session, matches),
+        Some("report") => report_time(&session, matches),
+        // shouldn't really ever get here
+        _ => Err(ErrorKind::Unexpected("unknown command".to_string()).into()),
+    }
+}
+
+fn report(ok: bool, message: &str) -> bool {
+    println!("{:<5} {}", if ok { "ok" } else { "FAIL" }, message);
+    ok
+}
+
+fn check(result: error::Result<bool>, message: &str) -> bool {
+    match result {
+        Ok(ok) => report(ok, message),
+        Err(why) => report(false, &format!("{}: {}", message, why)),
     }
 }
 
-fn issue(config: &Config, jira: &Jira, matches: &ArgMatches) {
-    let subcmd = match matches.subcommand_matches("issue") {
-        Some(matches) => matches,
-        None => util::exit("this should not be possible"),
+fn validate_config(
+    config_path: &Path,
+    profile: Option<&str>,
+    overrides: &[String],
+) -> error::Result<()> {
+    let config = match Config::new(config_path, profile, overrides) {
+        Err(why) => {
+            report(false, &format!("load {}: {}", config_path.display(), why));
+            return Err(why);
+        }
+        Ok(config) => config,
     };
+    report(true, &format!("load {}", config_path.display()));
 
-    let issue_key = subcmd.value_of("issue").unwrap();
-    let issue = match jira.issue(issue_key) {
-        Err(why) => util::exit(&format!("Error finding issue {}: {}", issue_key, why)),
-        Ok(issue) => issue,
+    let login = format!("log in to {} as {}", config.jira_url, config.username);
+    let jira = match Jira::from_config(&config) {
+        Err(why) => {
+            report(false, &format!("{}: {}", login, why));
+            return Err(why);
+        }
+        Ok(jira) => jira,
     };
+    report(true, &login);
+
+    let mut valid = true;
+
+    let mut projects = config.projects.clone();
+    if !projects.contains(&config.defaults.project_key) {
+        projects.push(config.defaults.project_key.clone());
+    }
+    for project in &projects {
+        valid &= check(
+            jira.project_exists(project),
+            &format!("project {} exists", project),
+        );
+    }
+
+    let mut users = config.npc_users.clone();
+    if !users.contains(&config.defaults.assignee) {
+        users.push(config.defaults.assignee.clone());
+    }
+    for user in users
+        .iter()
+        .filter(|user| jql::Value::user(user) != jql::Value::Empty)
+    {
+        valid &= check(jira.user_exists(user), &format!("user {} exists", user));
+    }
+
+    if !valid {
+        return Err(ErrorKind::ValidationFailed.into());
+    }
+    Ok(())
+}
+
+fn issue(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "issue")?;
+
+    let issue_key = session.issue_key_or_branch(subcmd.value_of("issue"))?;
+    let mut issue = session.issue(&issue_key)?;
+
+    // links to web pages are a separate request, and not worth failing over
+    if let Ok(jira) = session.jira("issue") {
+        match jira.remote_links(&issue.key) {
+            Ok(links) => issue.links.extend(links),
+            Err(why) => warn!("could not look up the links of {}: {}", issue.key, why),
+        }
+    }
 
     issue.print_tty(false);
 
-    if subcmd.is_present("open") {
-        util::open_in_browser(config, &issue)
+    if subcmd.is_present("worklogs") {
+        let worklogs = session.jira("issue --worklogs")?.worklogs(&issue.key)?;
+        println!();
+        if worklogs.is_empty() {
+            println!("No time logged");
+        } else {
+            let mut table = util::plain_table(&["started", "author", "time", "comment"]);
+            let mut total = 0;
+            for worklog in &worklogs {
+                total += worklog.timeSpentSeconds;
+                table.add_row(Row::new(vec![
+                    Cell::new(&util::local_time(&worklog.started)),
+                    Cell::new(&worklog.author.display_name()),
+                    Cell::new(&duration::format(worklog.timeSpentSeconds)),
+                    Cell::new(worklog.comment.as_ref().map_or("", |c| c.as_str())),
+                ]));
+            }
+            table.print_tty(false);
+            println!("Total: {}", duration::format(total));
+        }
+    }
+
+    if global_flag(matches, "open") {
+        util::open_in_browser(&session.config, &issue)?;
+    }
+    Ok(())
+}
+
+fn log_work(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "log")?;
+
+    // with a single argument it's the duration, for the active issue
+    let (issue_key, raw_duration) = match (subcmd.value_of("issue"), subcmd.value_of("duration")) {
+        (Some(key), Some(raw)) => (session.issue_key(Some(key))?, raw),
+        (Some(raw), None) => (session.issue_key(None)?, raw),
+        _ => return Err(ErrorKind::Unexpected("missing duration".to_string()).into()),
+    };
+    let seconds = duration::parse(raw_duration)?;
+    let started = match subcmd.value_of("started") {
+        Some(raw) => Some(util::parse_started(raw)?),
+        None => None,
+    };
+
+    session
+        .jira("log")?
+        .add_worklog(&issue_key, seconds, subcmd.value_of("comment"), started)?;
+    println!("Logged {} on {}", duration::format(seconds), issue_key);
+    Ok(())
+}
+
+fn report_time(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "report")?;
+    let time = subcommand(subcmd, "time")?;
+    let jira = session.jira("report")?;
+
+    let since = duration::parse_calendar(time.value_of("since").unwrap())?;
+    let cutoff = Local::now() - since;
+    let query = format!(
+        "worklogAuthor = currentUser() AND worklogDate >= {}",
+        jql::quote(&format!("-{}m", since.num_minutes()))
+    );
+
+    let me = jira.myself()?;
+    let mut table = util::plain_table(&["key", "summary", "time"]);
+    let mut total = 0;
+    for (issue, worklogs) in jira.query_worklogs(&query)? {
+        // the query finds issues with any of my recent work, only count the
+        // worklogs that are actually mine and recent
+        let spent: u64 = worklogs
+            .iter()
+            .filter(|worklog| worklog.author.is(&me))
+            .filter(|worklog| {
+                DateTime::parse_from_str(&worklog.started, JIRA_TIME_FORMAT)
+                    .map(|started| started.with_timezone(&Local) >= cutoff)
+                    .unwrap_or(false)
+            })
+            .map(|worklog| worklog.timeSpentSeconds)
+            .sum();
+        if spent > 0 {
+            total += spent;
+            table.add_row(Row::new(vec![
+                Cell::new(&issue.key),
+                Cell::new(&issue.summary),
+                Cell::new(&duration::format(spent)),
+            ]));
+        }
+    }
+
+    if total == 0 {
+        eprintln!("No time logged since {}", cutoff.format("%Y-%m-%d %H:%M"));
+        return Ok(());
     }
+    table.print_tty(false);
+    println!("Total: {}", duration::format(total));
+    Ok(())
 }
 
-fn query_helper(config: &Config, jira: &Jira, query: &str, output_columns: &[&str], prompt: bool) {
-    let issues = util::perform_query(jira, query);
+fn query_helper(
+    session: &Session,
+    matches: &ArgMatches,
+    query: &str,
+    output_columns: &[&str],
+) -> error::Result<()> {
+    let issues = session.query(query, global_flag(matches, "fail_on_empty"))?;
     util::render_issues(&issues, |result| {
         result.as_filtered_table(output_columns)
     });
 
-    if prompt {
-        let issue = util::prompt_for_issue(&issues);
-        util::open_in_browser(config, issue);
+    if global_flag(matches, "open") && !issues.is_empty() {
+        let issue = util::prompt_for_issue(&issues)?;
+        util::open_in_browser(&session.config, issue)?;
+    }
+    Ok(())
+}
+
+// the statuses start, stop and close move an issue to, in order of
+// preference since workflows differ in what they call things
+const START_STATUSES: &[&str] = &["In Progress"];
+const STOP_STATUSES: &[&str] = &["Open", "To Do", "Backlog"];
+const CLOSE_STATUSES: &[&str] = &["Closed", "Resolved", "Done"];
+
+// moves an issue to one of the statuses, unless it's already in one
+fn move_issue(jira: &Jira, issue_key: &str, statuses: &[&str]) -> error::Result<String> {
+    let issue = jira.issue(issue_key)?;
+    if statuses
+        .iter()
+        .any(|status| status.eq_ignore_ascii_case(&issue.status))
+    {
+        return Ok(issue.status);
+    }
+    jira.transition_to(issue_key, statuses)
+}
+
+// offers the time on a stopped timer as a worklog, `answer` coming from
+// --log/--no-log
+fn offer_worklog(jira: &Jira, timer: &Timer, answer: Option<bool>) -> error::Result<()> {
+    let seconds = timer.elapsed() / 60 * 60;
+    if seconds == 0 {
+        println!("Less than a minute on {}, nothing to log", timer.issue);
+        return Ok(());
+    }
+
+    let spent = duration::format(seconds);
+    let log = match answer {
+        Some(answer) => answer,
+        None => util::confirm(&format!("Log {} on {}?", spent, timer.issue))?,
+    };
+    if log {
+        let started = timer.started_at.with_timezone(&Local);
+        jira.add_worklog(&timer.issue, seconds, None, Some(started))?;
+        println!("Logged {} on {}", spent, timer.issue);
+    }
+    Ok(())
+}
+
+fn log_answer(subcmd: &ArgMatches) -> Option<bool> {
+    if subcmd.is_present("log") {
+        Some(true)
+    } else if subcmd.is_present("no_log") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn start(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "start")?;
+    let jira = session.jira("start")?;
+    let issue_key = subcmd.value_of("issue").unwrap().to_uppercase();
+
+    let mut state = session.load_state()?;
+    if let Some(ref timer) = state.timer {
+        if timer.issue == issue_key {
+            println!(
+                "Already working on {} for {}",
+                issue_key,
+                duration::format(timer.elapsed())
+            );
+            return Ok(());
+        }
+    }
+
+    let status = move_issue(jira, &issue_key, START_STATUSES)?;
+
+    // only one interrupt at a time, the previous one is put down
+    if let Some(timer) = state.timer.take() {
+        println!("Stopping the timer on {}", timer.issue);
+        session.save_state(&state)?;
+        offer_worklog(jira, &timer, None)?;
+    }
+
+    state.timer = Some(Timer::new(&issue_key));
+    state.activate(&issue_key);
+    session.save_state(&state)?;
+    println!("{} is {}, timer started", issue_key, status);
+    Ok(())
+}
+
+fn stop(
+    session: &Session,
+    matches: &ArgMatches,
+    name: &str,
+    statuses: &[&str],
+) -> error::Result<()> {
+    let subcmd = subcommand(matches, name)?;
+    let jira = session.jira(name)?;
+
+    let issue_key = session.issue_key(subcmd.value_of("issue"))?;
+
+    let status = move_issue(jira, &issue_key, statuses)?;
+    println!("{} is {}", issue_key, status);
+
+    let mut state = session.load_state()?;
+    // a closed issue is done with, there's nothing left to default to
+    if name == "close" && state.active.as_ref() == Some(&issue_key) {
+        state.active = None;
+    }
+    let timed = state
+        .timer
+        .as_ref()
+        .map_or(false, |timer| timer.issue == issue_key);
+    let timer = if timed { state.timer.take() } else { None };
+    session.save_state(&state)?;
+
+    if let Some(timer) = timer {
+        offer_worklog(jira, &timer, log_answer(subcmd))?;
+    }
+    Ok(())
+}
+
+fn comment(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "comment")?;
+    let jira = session.jira("comment")?;
+
+    // with a single argument it's the comment, for the active issue
+    let (issue_key, body) = match (subcmd.value_of("issue"), subcmd.value_of("body")) {
+        (Some(key), Some(body)) => (session.issue_key(Some(key))?, body.to_string()),
+        (Some(body), None) => (session.issue_key(None)?, body.to_string()),
+        (None, _) => (session.issue_key(None)?, Prompt::new().execute()?),
+    };
+    if body.trim().is_empty() {
+        return Err(ErrorKind::Unexpected("the comment is empty".to_string()).into());
+    }
+
+    jira.add_comment(&issue_key, &body)?;
+    println!("Commented on {}", issue_key);
+    Ok(())
+}
+
+fn label(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "label")?;
+    let jira = session.jira("label")?;
+
+    // without an issue key every argument is a change, for the active issue
+    let first = subcmd.value_of("issue").unwrap();
+    let mut changes = subcmd.values_of_lossy("changes").unwrap_or_default();
+    let issue_key = if first.starts_with('+') || first.starts_with('-') {
+        changes.insert(0, first.to_string());
+        session.issue_key(None)?
+    } else {
+        session.issue_key(Some(first))?
+    };
+    if changes.is_empty() {
+        return Err(ErrorKind::Unexpected("no labels to add or remove".to_string()).into());
     }
+
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    for change in &changes {
+        let (list, name) = if change.starts_with('+') {
+            (&mut add, &change[1..])
+        } else if change.starts_with('-') {
+            (&mut remove, &change[1..])
+        } else {
+            return Err(ErrorKind::InvalidLabelChange(change.clone()).into());
+        };
+        // jira labels can't contain spaces
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(ErrorKind::InvalidLabel(name.to_string()).into());
+        }
+        list.push(name.to_string());
+    }
+
+    jira.update_labels(&issue_key, &add, &remove)?;
+    let issue = session.issue(&issue_key)?;
+    if issue.labels.is_empty() {
+        println!("{} has no labels", issue.key);
+    } else {
+        println!("Labels on {}: {}", issue.key, issue.labels.join(", "));
+    }
+    Ok(())
 }
 
-fn list(config: &Config, jira: &Jira, matches: &ArgMatches) {
+fn labels(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "labels")?;
+
+    let projects = match subcmd.values_of_lossy("project") {
+        Some(projects) => jql::List::strings(&projects),
+        None => session.config.projects(),
+    };
     let query = format!(
-        "project in ({}) AND status not in (Resolved, Closed)",
-        config.projects()
+        "project in {} AND labels != EMPTY ORDER BY updated DESC",
+        projects
     );
-    let output_columns = ["key", "reporter", "assignee", "status", "summary"];
-    query_helper(config, jira, &query, &output_columns, matches.is_present("open"));
+    let issues = session.query(&query, global_flag(matches, "fail_on_empty"))?;
+    let counts = issues.label_counts();
+    if counts.is_empty() {
+        println!("No labels in use");
+        return Ok(());
+    }
+
+    let mut table = util::plain_table(&["label", "issues"]);
+    for (label, count) in counts {
+        table.add_row(Row::new(vec![Cell::new(&label), Cell::new(&count.to_string())]));
+    }
+    table.print_tty(false);
+    Ok(())
 }
 
-fn current(config: &Config, jira: &Jira, matches: &ArgMatches) {
-    let query = format!(
-        "project in ({}) AND assignee = {} AND status not in (Resolved, Closed)",
+fn switch(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "switch")?;
+    let mut state = session.load_state()?;
+
+    let issue_key = match subcmd.value_of("issue") {
+        Some(key) => key.to_uppercase(),
+        None => {
+            if state.history.is_empty() {
+                println!("No recently used issues");
+                return Ok(());
+            }
+            for (i, key) in state.history.iter().enumerate() {
+                let marker = if state.active.as_ref() == Some(key) { "*" } else { " " };
+                println!("{} {:>2} {}", marker, i + 1, key);
+            }
+            util::prompt_for_index("Switch to #: ", state.history.len())
+                .map(|idx| state.history[idx].clone())?
+        }
+    };
+
+    // make sure it exists before making it the default for everything else
+    let issue = session.issue(&issue_key)?;
+    state.activate(&issue.key);
+    session.save_state(&state)?;
+
+    println!("Switched to {}: {} ({})", issue.key, issue.summary, issue.status);
+    if let Some(ref timer) = state.timer {
+        if timer.issue != issue.key {
+            println!("The timer is still running on {}", timer.issue);
+        }
+    }
+    Ok(())
+}
+
+fn attach(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "attach")?;
+    let jira = session.jira("attach")?;
+
+    let issue_key = session.issue_key(subcmd.value_of("issue"))?;
+    let mut files = Vec::new();
+    for path in subcmd.values_of("file").unwrap() {
+        let path = Path::new(path);
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| ErrorKind::Unexpected(format!("{} is not a file", path.display())))?;
+        let mut contents = Vec::new();
+        File::open(path)?.read_to_end(&mut contents)?;
+        files.push((name, contents));
+    }
+
+    for attachment in jira.attach(&issue_key, &files)? {
+        println!(
+            "Attached {} ({}) to {}",
+            attachment.filename,
+            util::human_size(attachment.size),
+            issue_key
+        );
+    }
+    Ok(())
+}
+
+fn attachments(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "attachments")?;
+
+    let issue_key = session.issue_key(subcmd.value_of("issue"))?;
+    let issue = session.issue(&issue_key)?;
+    if issue.attachments.is_empty() {
+        println!("{} has no attachments", issue.key);
+        return Ok(());
+    }
+
+    if !subcmd.is_present("download") {
+        let mut table = util::plain_table(&["id", "file", "size", "author", "created"]);
+        for attachment in &issue.attachments {
+            table.add_row(Row::new(vec![
+                Cell::new(&attachment.id),
+                Cell::new(&attachment.filename),
+                Cell::new(&util::human_size(attachment.size)),
+                Cell::new(&attachment.author),
+                Cell::new(&util::local_time(&attachment.created)),
+            ]));
+        }
+        table.print_tty(false);
+        return Ok(());
+    }
+
+    let jira = session.jira("attachments --download")?;
+    let dir = Path::new(subcmd.value_of("dir").unwrap_or("."));
+    fs::create_dir_all(dir)?;
+    for attachment in &issue.attachments {
+        // the name comes from whoever uploaded it, don't let it leave the dir
+        let name = Path::new(&attachment.filename)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| attachment.id.clone().into());
+        let path = dir.join(name);
+        if path.exists() {
+            println!("Skipping {}, it already exists", path.display());
+            continue;
+        }
+        let contents = jira.download(attachment)?;
+        File::create(&path)?.write_all(&contents)?;
+        println!("Saved {} ({})", path.display(), util::human_size(attachment.size));
+    }
+    Ok(())
+}
+
+// the --type choices and the names of the link types jira ships with
+const LINK_TYPES: &[(&str, &str, &str)] = &[
+    ("blocks", "Blocks", "blocks"),
+    ("relates", "Relates", "relates to"),
+    ("duplicates", "Duplicate", "duplicates"),
+];
+
+fn link(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "link")?;
+    let jira = session.jira("link")?;
+
+    let issue_key = session.issue_key(subcmd.value_of("issue"))?;
+    let target = subcmd.value_of("target").unwrap();
+
+    if target.contains("://") {
+        if subcmd.is_present("type") {
+            return Err(ErrorKind::InvalidLink(
+                "--type only applies to links between issues".to_string(),
+            ).into());
+        }
+        let title = subcmd.value_of("title").unwrap_or(target);
+        jira.add_remote_link(&issue_key, target, title)?;
+        println!("Linked {} to {}", issue_key, target);
+        return Ok(());
+    }
+
+    if subcmd.is_present("title") {
+        return Err(ErrorKind::InvalidLink(
+            "--title only applies to links to urls".to_string(),
+        ).into());
+    }
+    let other = session.issue_key(Some(target))?;
+    let choice = subcmd.value_of("type").unwrap_or("relates");
+    let &(_, link_type, description) = LINK_TYPES
+        .iter()
+        .find(|&&(name, _, _)| name == choice)
+        .ok_or_else(|| ErrorKind::InvalidLink(format!("unknown link type '{}'", choice)))?;
+    jira.link_issues(link_type, &issue_key, &other)?;
+    println!("{} {} {}", issue_key, description, other);
+    Ok(())
+}
+
+fn branch(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "branch")?;
+
+    let issue_key = session.issue_key(subcmd.value_of("issue"))?;
+    let issue = session.issue(&issue_key)?;
+    let name = git::branch_name(&session.config.branch_template, &issue);
+
+    if git::checkout(&name)? {
+        println!("Switched to a new branch '{}'", name);
+    } else {
+        println!("Switched to branch '{}'", name);
+    }
+
+    let mut state = session.load_state()?;
+    state.activate(&issue.key);
+    session.save_state(&state)
+}
+
+fn status(session: &Session) -> error::Result<()> {
+    let state = session.load_state()?;
+    let active = match state
+        .active
+        .clone()
+        .or_else(|| state.timer.as_ref().map(|timer| timer.issue.clone()))
+    {
+        Some(active) => active,
+        None => {
+            println!("No interrupt in progress");
+            return Ok(());
+        }
+    };
+
+    // the status comes from jira, the one in the history could be stale
+    let timed = match state.timer {
+        Some(ref timer) if timer.issue == active => {
+            format!(" for {}", duration::format(timer.elapsed()))
+        }
+        _ => String::new(),
+    };
+    match session.issue(&active) {
+        Ok(issue) => println!(
+            "{}: {} ({}){}",
+            issue.key, issue.summary, issue.status, timed
+        ),
+        Err(why) => {
+            warn!("could not look up {}: {}", active, why);
+            println!("{}{}", active, timed);
+        }
+    }
+
+    if let Some(ref timer) = state.timer {
+        if timer.issue != active {
+            println!(
+                "The timer is running on {} for {}",
+                timer.issue,
+                duration::format(timer.elapsed())
+            );
+        }
+    }
+
+    let recent: Vec<&str> = state
+        .history
+        .iter()
+        .filter(|key| **key != active)
+        .map(|key| key.as_str())
+        .collect();
+    if !recent.is_empty() {
+        println!("Recent: {}", recent.join(", "));
+    }
+    Ok(())
+}
+
+fn closed_statuses() -> jql::List {
+    jql::List::strings(&["Resolved", "Closed"])
+}
+
+fn list_query(config: &Config) -> String {
+    format!(
+        "project in {} AND status not in {}",
         config.projects(),
-        config.username
-    );
-    let output_columns = ["key", "reporter", "status", "summary"];
-    query_helper(config, jira, &query, &output_columns, matches.is_present("open"));
+        closed_statuses()
+    )
 }
 
-fn next(config: &Config, jira: &Jira, matches: &ArgMatches) {
-    let query = format!(
-        "project in ({}) AND status = Open AND assignee in ({})",
+fn current_query(config: &Config) -> String {
+    format!(
+        "project in {} AND assignee = {} AND status not in {}",
         config.projects(),
+        config.username(),
+        closed_statuses()
+    )
+}
+
+fn next_query(config: &Config) -> String {
+    format!(
+        "project in {} AND status = {} AND assignee in {}",
+        config.projects(),
+        jql::Value::string("Open"),
         config.npc_users()
-    );
+    )
+}
+
+fn list(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let query = list_query(&session.config);
+    let output_columns = ["key", "reporter", "assignee", "status", "summary"];
+    query_helper(session, matches, &query, &output_columns)
+}
+
+fn current(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let query = current_query(&session.config);
+    let output_columns = ["key", "reporter", "status", "summary"];
+    query_helper(session, matches, &query, &output_columns)
+}
+
+fn next(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let query = next_query(&session.config);
     let output_columns = ["key", "reporter", "summary"];
-    query_helper(config, jira, &query, &output_columns, matches.is_present("open"));
+    query_helper(session, matches, &query, &output_columns)
 }
 
-fn new(config: &Config, jira: &Jira, matches: &ArgMatches, debug: bool) {
-    let subcmd = match matches.subcommand_matches("new") {
-        Some(matches) => matches,
-        None => util::exit("this should not be possible"),
-    };
+// the built in queries can be used by name, anything else is jql
+fn named_query(config: &Config, query: &str) -> String {
+    match query {
+        "list" => list_query(config),
+        "current" => current_query(config),
+        "next" => next_query(config),
+        query => query.to_string(),
+    }
+}
+
+fn watch(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "watch")?;
+    session.jira("watch")?;
+
+    let query = named_query(&session.config, subcmd.value_of("query").unwrap_or("next"));
+    // defaults to 60 in app.yml
+    let interval: u64 = number_value(subcmd, "interval")?.unwrap_or(60);
+    let count: Option<u64> = number_value(subcmd, "count")?;
+    let notify = subcmd.is_present("notify");
+    let hook = subcmd.value_of("hook");
+
+    let mut previous = session.query(&query, false)?;
+    eprintln!(
+        "Watching {} issue(s) matching: {}",
+        previous.iter().count(),
+        query
+    );
+
+    let mut polls = 1;
+    while count.map_or(true, |count| polls < count) {
+        thread::sleep(Duration::from_secs(interval));
+        polls += 1;
+
+        // a flaky connection shouldn't end the watch, try again next time
+        let current = match session.query(&query, false) {
+            Ok(current) => current,
+            Err(why) => {
+                warn!("could not refresh the query: {}", why);
+                continue;
+            }
+        };
+
+        for change in watch::diff(&previous, &current) {
+            println!("{} {}", Local::now().format("%H:%M:%S"), change);
+            if notify {
+                util::notify_desktop(&change);
+            }
+            if let Some(hook) = hook {
+                util::run_hook(hook, &change);
+            }
+        }
+        previous = current;
+    }
+    Ok(())
+}
 
+fn new(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "new")?;
+    let config = &session.config;
+
+    let file = read_issue_file(subcmd)?;
+    let jira = session.jira("new")?;
+
+    // the command line wins over the file, which wins over the config
+    let parent = subcmd
+        .value_of("parent")
+        .map(|key| key.to_string())
+        .or(file.parent)
+        .map(|key| key.to_uppercase());
+    let epic = subcmd
+        .value_of("epic")
+        .map(|key| key.to_string())
+        .or(file.epic)
+        .map(|key| key.to_uppercase());
+    // clap only stops both being given on the command line
+    if parent.is_some() && epic.is_some() {
+        return Err(ErrorKind::InvalidIssueFile(
+            "an issue can have a parent or an epic, not both".to_string(),
+        ).into());
+    }
+    // subtasks live in their parent's project
     let project = subcmd
         .value_of("project")
-        .unwrap_or(config.defaults.project_key.as_str());
-    let summary = subcmd.value_of("summary").unwrap();
+        .map(|project| project.to_string())
+        .or(file.project);
+    let project = match (project, parent.as_ref()) {
+        (Some(project), _) => project,
+        (None, Some(parent)) => parent.split('-').next().unwrap_or("").to_string(),
+        (None, None) => config.defaults.project_key.clone(),
+    };
+    let summary = subcmd
+        .value_of("summary")
+        .map(|summary| summary.to_string())
+        .or(file.summary);
+    let summary = match summary {
+        Some(summary) => summary,
+        None => {
+            return Err(ErrorKind::InvalidIssueFile("there's no summary".to_string()).into())
+        }
+    };
     let assignee = subcmd
         .value_of("assignee")
-        .unwrap_or(config.defaults.assignee.as_str());
+        .map(|assignee| assignee.to_string())
+        .or(file.assignee)
+        .unwrap_or_else(|| config.defaults.assignee.clone());
 
-    let labels = match subcmd.values_of_lossy("label") {
-        Some(labels) => labels,
-        None => config.defaults.labels.to_owned(),
-    };
+    let labels = subcmd
+        .values_of_lossy("label")
+        .or(file.labels)
+        .unwrap_or_else(|| config.defaults.labels.to_owned());
 
-    let mut description = subcmd.value_of("description").unwrap_or("").to_string();
+    let mut description = subcmd
+        .value_of("description")
+        .map(|description| description.to_string())
+        .or(file.description)
+        .unwrap_or_default();
     if subcmd.is_present("long_description") {
-        description = match Prompt::new().execute() {
-            Err(why) => util::exit(&format!("Failed to get description from editor: {}", why)),
-            Ok(description) => description,
-        };
+        description = Prompt::new().execute()?;
     }
 
-    let issue = match jira.create_issue(project, summary, description.as_str(), assignee, &labels, debug) {
-        Err(why) => util::exit(&format!("Error creating issue \"{}\": {}", summary, why)),
-        Ok(issue) => issue,
-    };
+    let issue = jira.create_issue(&NewIssue {
+        project: project,
+        summary: summary,
+        description: description,
+        assignee: assignee,
+        labels: labels,
+        parent: parent,
+        epic: epic,
+        fields: file.fields,
+    })?;
 
     issue.print_tty(false);
 
     if config.open_in_browser {
-        util::open_in_browser(config, &issue)
+        util::open_in_browser(config, &issue)?;
     }
+    Ok(())
 }
 
-fn jql(config: &Config, jira: &Jira, matches: &ArgMatches) {
-    let subcmd = match matches.subcommand_matches("jql") {
-        Some(matches) => matches,
-        None => util::exit("this should not be possible"),
-    };
+/// Reads `--from-file` or `--stdin`, an empty `IssueFile` without either.
+fn read_issue_file(subcmd: &ArgMatches) -> error::Result<IssueFile> {
+    let mut text = String::new();
+    let path = subcmd.value_of("from_file");
+    match path {
+        Some(path) => {
+            File::open(path)?.read_to_string(&mut text)?;
+        }
+        None if subcmd.is_present("stdin") => {
+            io::stdin().read_to_string(&mut text)?;
+        }
+        None => return Ok(IssueFile::default()),
+    }
+
+    let format = subcmd
+        .value_of("format")
+        .and_then(Format::from_name)
+        .or_else(|| path.and_then(|path| Format::from_path(Path::new(path))))
+        .or_else(|| Format::sniff(&text));
+    match format {
+        Some(format) => issue_file::parse(&text, format),
+        None => Err(ErrorKind::InvalidIssueFile(
+            "can't tell if it's yaml, json or markdown, use --format".to_string(),
+        ).into()),
+    }
+}
+
+fn bulk(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "bulk")?;
+    let jira = session.jira("bulk")?;
+
+    let mut operations = Vec::new();
+    if let Some(username) = subcmd.value_of("assign") {
+        operations.push(Operation::Assign(username.to_string()));
+    }
+    for label in subcmd.values_of_lossy("add_label").unwrap_or_default() {
+        operations.push(Operation::AddLabel(label));
+    }
+    if let Some(body) = subcmd.value_of("comment") {
+        operations.push(Operation::Comment(body.to_string()));
+    }
+    // last, since some workflows don't allow editing closed issues
+    if let Some(status) = subcmd.value_of("transition") {
+        operations.push(Operation::Transition(status.to_string()));
+    }
+    // defaults to 4 in app.yml
+    let concurrency = number_value(subcmd, "concurrency")?.unwrap_or(4);
+
+    let query = named_query(&session.config, subcmd.value_of("query").unwrap());
+    let issues = session.query(&query, global_flag(matches, "fail_on_empty"))?;
+    util::render_issues(&issues, |result| result.as_table());
+    if issues.is_empty() {
+        return Ok(());
+    }
+
+    let changes: Vec<String> = operations.iter().map(|op| op.to_string()).collect();
+    let question = format!(
+        "Change {} issue(s): {}?",
+        issues.iter().count(),
+        changes.join(", ")
+    );
+    if !subcmd.is_present("yes") && !util::confirm(&question)? {
+        println!("Nothing was changed");
+        return Ok(());
+    }
+
+    let issues = issues.into_vec();
+    let outcomes = bulk::run(jira, &issues, &operations, concurrency);
+    let mut failed = 0;
+    for (issue, outcome) in issues.iter().zip(outcomes) {
+        match outcome {
+            Ok(()) => report(true, &issue.key),
+            Err(why) => {
+                failed += 1;
+                report(false, &format!("{}: {}", issue.key, why))
+            }
+        };
+    }
+    println!("Changed {} of {} issues", issues.len() - failed, issues.len());
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(ErrorKind::BulkFailed(failed, issues.len()).into()),
+    }
+}
+
+fn import(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "import")?;
+    let config = &session.config;
+    let dry_run = subcmd.is_present("dry_run");
+
+    let path = Path::new(subcmd.value_of("file").unwrap());
+    let format = subcmd
+        .value_of("format")
+        .and_then(import::Format::from_name)
+        .or_else(|| import::Format::from_path(path))
+        .ok_or_else(|| {
+            ErrorKind::InvalidImportFile(format!(
+                "can't tell what kind of file {} is, use --format",
+                path.display()
+            ))
+        })?;
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    let rows = import::read(&text, format)?;
+    if rows.is_empty() {
+        return Err(ErrorKind::InvalidImportFile(format!("{} has no rows", path.display())).into());
+    }
+
+    let jira = session.jira("import")?;
+    let meta = jira.create_meta(&import::projects(&rows, &config.defaults.project_key))?;
+    let importer = import::Importer::new(&meta, config);
+
+    let mut valid = Vec::new();
+    let mut invalid = 0;
+    for row in &rows {
+        match importer.fields(row) {
+            Ok(fields) => {
+                if dry_run {
+                    println!(
+                        "row {}: ok, a {} in {}: {}",
+                        row.number,
+                        fields["issuetype"]["name"].as_str().unwrap_or(""),
+                        fields["project"]["key"].as_str().unwrap_or(""),
+                        fields["summary"].as_str().unwrap_or("")
+                    );
+                }
+                valid.push((row.number, fields));
+            }
+            Err(problems) => {
+                invalid += 1;
+                for problem in problems {
+                    println!("row {}: {}", row.number, problem);
+                }
+            }
+        }
+    }
+
+    if dry_run {
+        println!("{} of {} rows can be imported", valid.len(), rows.len());
+    } else if invalid > 0 {
+        // half an import is harder to clean up than none at all
+        println!("Nothing was imported, fix the rows above first");
+    }
+    if dry_run || invalid > 0 {
+        return match invalid {
+            0 => Ok(()),
+            _ => Err(ErrorKind::ImportFailed(invalid, rows.len()).into()),
+        };
+    }
+
+    let fields: Vec<serde_json::Value> = valid
+        .iter()
+        .map(|&(_, ref fields)| fields.clone())
+        .collect();
+    let results = jira.create_issues(&fields)?;
+    let mut failed = 0;
+    for (&(number, ref fields), result) in valid.iter().zip(results) {
+        match result {
+            Ok(key) => println!(
+                "row {}: created {} {}",
+                number,
+                key,
+                fields["summary"].as_str().unwrap_or("")
+            ),
+            Err(why) => {
+                failed += 1;
+                println!("row {}: {}", number, why);
+            }
+        }
+    }
+    println!("Created {} of {} issues", rows.len() - failed, rows.len());
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(ErrorKind::ImportFailed(failed, rows.len()).into()),
+    }
+}
+
+fn jql(session: &Session, matches: &ArgMatches) -> error::Result<()> {
+    let subcmd = subcommand(matches, "jql")?;
 
     let query = subcmd.value_of("query").unwrap();
-    let issues = util::perform_query(jira, query);
+    let issues = session.query(query, global_flag(matches, "fail_on_empty"))?;
     util::render_issues(&issues, |result| {
         if subcmd.is_present("url") {
             result.as_filtered_table(&["key", "browse_url"])
@@ -216,8 +1243,9 @@ fn jql(config: &Config, jira: &Jira, matches: &ArgMatches) {
         }
     });
 
-    if matches.is_present("open") {
-        let issue = util::prompt_for_issue(&issues);
-        util::open_in_browser(config, issue);
+    if global_flag(matches, "open") && !issues.is_empty() {
+        let issue = util::prompt_for_issue(&issues)?;
+        util::open_in_browser(&session.config, issue)?;
     }
+    Ok(())
 }
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use serde_json;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{ErrorKind as IoErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use error::Result;
+
+/// A timer running against an interrupt, started by `ob start`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Timer {
+    pub issue: String,
+    pub started_at: DateTime<Utc>,
+}
+
+impl Timer {
+    pub fn new(issue: &str) -> Timer {
+        Timer {
+            issue: issue.to_string(),
+            started_at: Utc::now(),
+        }
+    }
+
+    /// Whole seconds since the timer was started.
+    pub fn elapsed(&self) -> u64 {
+        let seconds = (Utc::now() - self.started_at).num_seconds();
+        if seconds < 0 {
+            0
+        } else {
+            seconds as u64
+        }
+    }
+}
+
+/// How many recently touched issues are remembered.
+pub const HISTORY_LEN: usize = 10;
+
+/// What `ob` remembers between runs, kept as json in a small state file.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct State {
+    #[serde(default)]
+    pub timer: Option<Timer>,
+    /// The issue commands act on when they aren't given a key.
+    #[serde(default)]
+    pub active: Option<String>,
+    /// Recently touched issues, most recent first.
+    #[serde(default)]
+    pub history: Vec<String>,
+}
+
+impl State {
+    /// Makes the issue the active one and moves it to the top of the history.
+    pub fn activate(&mut self, issue: &str) {
+        self.active = Some(issue.to_string());
+        self.touch(issue);
+    }
+
+    /// Records the issue as recently touched without changing the active one.
+    pub fn touch(&mut self, issue: &str) {
+        self.history.retain(|key| key != issue);
+        self.history.insert(0, issue.to_string());
+        self.history.truncate(HISTORY_LEN);
+    }
+
+    /// `$XDG_STATE_HOME/ob/state.json`, falling back to
+    /// `~/.local/state/ob/state.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_STATE_HOME") {
+            Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ => env::home_dir().map(|home| home.join(".local").join("state")),
+        };
+        dir.map(|dir| dir.join("ob").join("state.json"))
+    }
+
+    /// Loads the state, a missing file being the same as an empty one.
+    pub fn load(path: &Path) -> Result<State> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(ref why) if why.kind() == IoErrorKind::NotFound => Ok(State::default()),
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_is_most_recent_first_without_duplicates() {
+        let mut state = State::default();
+        state.activate("FOO-1");
+        state.touch("FOO-2");
+        state.activate("FOO-1");
+        assert_eq!(state.active, Some("FOO-1".to_string()));
+        assert_eq!(state.history, vec!["FOO-1", "FOO-2"]);
+
+        for i in 0..20 {
+            state.touch(&format!("BAR-{}", i));
+        }
+        assert_eq!(state.history.len(), HISTORY_LEN);
+        assert_eq!(state.history[0], "BAR-19");
+    }
+
+    #[test]
+    fn older_state_files_still_load() {
+        let state: State = serde_json::from_str("{\"timer\":null}").unwrap();
+        assert_eq!(state, State::default());
+    }
+}
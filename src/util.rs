@@ -1,66 +1,313 @@
-extern crate clap;
-
+use std::env;
 use std::io;
 use std::io::Write;
-use std::process::Command;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use prettytable::Table;
+use prettytable::cell::Cell;
+use prettytable::format;
+use prettytable::row::Row;
+use serde_json;
+
+use oh_bother::cache::Cache;
+use oh_bother::config::Config;
+use oh_bother::error::{ErrorKind, Result};
+use oh_bother::git;
+use oh_bother::issue::{Issue, IssueVec};
+use oh_bother::jira::{Jira, JIRA_TIME_FORMAT};
+use oh_bother::state::State;
+use oh_bother::watch::Change;
 
-use config::Config;
-use jira::Jira;
-use issue::{Issue, IssueVec};
+/// What every command works with: the config, the local cache and, unless
+/// running with `--offline`, a logged in jira client.
+pub struct Session {
+    pub config: Config,
+    pub cache: Cache,
+    jira: Option<Jira>,
+    refresh: bool,
+    state_path: PathBuf,
+}
 
-pub fn exit(message: &str) -> ! {
-    let err = clap::Error::with_description(message, clap::ErrorKind::InvalidValue);
-    err.exit();
+fn offline_banner(cached_at: &DateTime<Utc>) {
+    eprintln!(
+        "Offline: showing results cached at {}",
+        cached_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
 }
 
-pub fn perform_query(jira: &Jira, query: &str) -> IssueVec {
-    let result = match jira.query(query) {
-        Err(why) => exit(&format!("Error executing query {}: {}", query, why)),
-        Ok(result) => result,
-    };
+impl Session {
+    pub fn new(config: Config, offline: bool, refresh: bool) -> Result<Session> {
+        let jira = if offline {
+            None
+        } else {
+            Some(Jira::from_config(&config)?)
+        };
+        let cache_dir = Cache::default_dir().unwrap_or_else(|| env::temp_dir().join("ob-cache"));
+        let cache = Cache::for_instance(cache_dir, &config.jira_url);
+        let state_path =
+            State::default_path().unwrap_or_else(|| env::temp_dir().join("ob-state.json"));
+        Ok(Session {
+            config: config,
+            cache: cache,
+            jira: jira,
+            refresh: refresh,
+            state_path: state_path,
+        })
+    }
+
+    pub fn load_state(&self) -> Result<State> {
+        State::load(&self.state_path)
+    }
+
+    pub fn save_state(&self, state: &State) -> Result<()> {
+        state.save(&self.state_path)
+    }
+
+    /// The issue a command should act on: the one it was given, which is
+    /// then remembered as recently touched, or else the active one.
+    pub fn issue_key(&self, explicit: Option<&str>) -> Result<String> {
+        let mut state = self.load_state()?;
+        match explicit {
+            Some(key) => {
+                let key = key.to_uppercase();
+                state.touch(&key);
+                self.save_state(&state)?;
+                Ok(key)
+            }
+            None => {
+                let timer_issue = state.timer.map(|timer| timer.issue);
+                state
+                    .active
+                    .or(timer_issue)
+                    .ok_or_else(|| ErrorKind::NoActiveIssue.into())
+            }
+        }
+    }
+
+    /// Like `issue_key`, except that without an explicit key the issue named
+    /// by the current git branch comes before the active one.
+    pub fn issue_key_or_branch(&self, explicit: Option<&str>) -> Result<String> {
+        if explicit.is_none() {
+            let from_branch = git::current_branch()
+                .and_then(|branch| git::issue_key_from_branch(&branch, &self.config.projects));
+            if let Some(key) = from_branch {
+                return Ok(key);
+            }
+        }
+        self.issue_key(explicit)
+    }
+
+    /// The jira client, for commands that can't do their job from the cache.
+    pub fn jira(&self, command: &str) -> Result<&Jira> {
+        self.jira
+            .as_ref()
+            .ok_or_else(|| ErrorKind::Offline(command.to_string()).into())
+    }
+
+    /// Runs a query, or looks it up in the cache when offline. An empty result
+    /// is only an error when `fail_on_empty` is set.
+    pub fn query(&self, query: &str, fail_on_empty: bool) -> Result<IssueVec> {
+        let result = match self.jira {
+            Some(ref jira) => self.cache.sync_query(jira, query, self.refresh)?,
+            None => match self.cache.query(query) {
+                Some(cached) => {
+                    offline_banner(&cached.synced_at);
+                    IssueVec::from(cached.issues)
+                }
+                None => {
+                    return Err(ErrorKind::NotCached(format!("the query \"{}\"", query)).into())
+                }
+            },
+        };
 
-    if result.is_empty() {
-        exit(&format!("the query \"{}\" returned no issues", query));
+        if result.is_empty() && fail_on_empty {
+            return Err(ErrorKind::EmptyResult(query.to_string()).into());
+        }
+
+        Ok(result)
     }
 
-    result
+    /// Fetches an issue, or looks it up in the cache when offline.
+    pub fn issue(&self, key: &str) -> Result<Issue> {
+        match self.jira {
+            Some(ref jira) => self.cache.fetch_issue(jira, key),
+            None => match self.cache.issue(key) {
+                Some(cached) => {
+                    offline_banner(&cached.fetched_at);
+                    Ok(cached.issue)
+                }
+                None => Err(ErrorKind::NotCached(format!("issue {}", key)).into()),
+            },
+        }
+    }
 }
 
 pub fn render_issues<F>(issues: &IssueVec, table_fn: F)
 where
     F: Fn(&IssueVec) -> Table,
 {
+    if issues.is_empty() {
+        eprintln!("No issues found");
+        return;
+    }
     table_fn(issues).print_tty(false);
 }
 
-pub fn prompt_for_issue(issues: &IssueVec) -> &Issue {
-    print!("Open issue #: ");
-    match io::stdout().flush() {
-        Err(why) => exit(&format!("Error flushing output: {}", why)),
-        _ => {}
-    }; // need to do this since print! won't flush
+/// Asks a yes/no question, where just pressing enter means yes. No input at
+/// all (stdin closed) means no, so scripts never log work by accident.
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [Y/n] ", question);
+    io::stdout().flush()?;
     let mut raw_input = String::new();
-    io::stdin()
-        .read_line(&mut raw_input)
-        .expect("Invalid team username");
+    if io::stdin().read_line(&mut raw_input)? == 0 {
+        println!();
+        return Ok(false);
+    }
+    Ok(match raw_input.trim().to_lowercase().as_str() {
+        "" | "y" | "yes" => true,
+        _ => false,
+    })
+}
+
+/// Asks for one of `count` numbered choices and returns its (zero based)
+/// index.
+pub fn prompt_for_index(prompt: &str, count: usize) -> Result<usize> {
+    print!("{}", prompt);
+    io::stdout().flush()?; // need to do this since print! won't flush
+    let mut raw_input = String::new();
+    io::stdin().read_line(&mut raw_input)?;
     let trimmed = raw_input.trim();
-    let idx = match trimmed.parse::<usize>() {
-        Ok(val) => val - 1,
-        Err(why) => exit(&format!("Error parsing input: {}", why)),
-    };
-    match issues.get(idx) {
-        Some(issue) => issue,
-        None => exit(&format!("Could not find issue with index: {}", idx)),
-    }
-}
-
-pub fn open_in_browser(config: &Config, issue: &Issue) {
-    match Command::new(config.browser_command.as_str())
-        .arg(&issue.browse_url)
-        .output()
-    {
-        Err(why) => exit(&format!("Error opening in browser: {}", why)),
-        _ => {}
+    match trimmed.parse::<usize>() {
+        Ok(val) if val > 0 && val <= count => Ok(val - 1),
+        _ => Err(ErrorKind::InvalidSelection(trimmed.to_string()).into()),
+    }
+}
+
+pub fn prompt_for_issue(issues: &IssueVec) -> Result<&Issue> {
+    let idx = prompt_for_index("Open issue #: ", issues.iter().count())?;
+    issues
+        .get(idx)
+        .ok_or_else(|| ErrorKind::Unexpected("selection out of range".to_string()).into())
+}
+
+pub fn open_in_browser(config: &Config, issue: &Issue) -> Result<()> {
+    issue.open_in_browser(&config.browser_command)
+}
+
+#[cfg(target_os = "macos")]
+fn notification_command(title: &str, message: &str) -> Command {
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {:?} with title {:?}",
+        message, title
+    ));
+    command
+}
+
+#[cfg(not(target_os = "macos"))]
+fn notification_command(title: &str, message: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.arg(title).arg(message);
+    command
+}
+
+/// Shows a desktop notification for a change. Failing to notify is only
+/// worth a warning, the change has already been printed.
+pub fn notify_desktop(change: &Change) {
+    let title = format!("ob: {}", change.issue().key);
+    if let Err(why) = notification_command(&title, &change.to_string()).output() {
+        warn!("could not show a notification: {}", why);
+    }
+}
+
+/// Runs a user supplied shell command with the changed issue as json on
+/// stdin and the kind of change in `OB_EVENT`.
+pub fn run_hook(hook: &str, change: &Change) {
+    let result = serde_json::to_string(change.issue())
+        .map_err(|why| why.to_string())
+        .and_then(|json| {
+            let mut child = Command::new("sh")
+                .arg("-c")
+                .arg(hook)
+                .env("OB_EVENT", change.event())
+                .env("OB_ISSUE", &change.issue().key)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|why| why.to_string())?;
+            // a hook that doesn't read the issue closes the pipe early, which
+            // is fine, and the child is waited for either way
+            let written = match child.stdin.take() {
+                Some(mut stdin) => match stdin.write_all(json.as_bytes()) {
+                    Err(ref why) if why.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                    written => written,
+                },
+                None => Ok(()),
+            };
+            let status = child.wait().map_err(|why| why.to_string())?;
+            if let Err(why) = written {
+                warn!("could not send the issue to hook '{}': {}", hook, why);
+            }
+            Ok(status)
+        });
+
+    match result {
+        Ok(ref status) if status.success() => {}
+        Ok(status) => warn!("hook '{}' failed: {}", hook, status),
+        Err(why) => warn!("could not run hook '{}': {}", hook, why),
+    }
+}
+
+/// A table in the same style as the issue lists.
+pub fn plain_table(titles: &[&str]) -> Table {
+    let mut table = Table::new();
+    table.set_format(
+        format::FormatBuilder::new()
+            .padding(1, 1)
+            .separator(
+                format::LinePosition::Title,
+                format::LineSeparator::new('-', '-', '-', '-'),
+            )
+            .build(),
+    );
+    table.set_titles(Row::new(titles.iter().map(|title| Cell::new(title)).collect()));
+    table
+}
+
+/// Renders one of jira's timestamps in local time, or as-is if it can't be
+/// parsed.
+pub fn local_time(jira_time: &str) -> String {
+    match DateTime::parse_from_str(jira_time, JIRA_TIME_FORMAT) {
+        Ok(time) => time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+        Err(_) => jira_time.to_string(),
+    }
+}
+
+/// Parses `YYYY-MM-DD HH:MM`, or `HH:MM` for today, as local time.
+pub fn parse_started(raw: &str) -> Result<DateTime<Local>> {
+    let raw = raw.trim();
+    let naive = NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M").or_else(|_| {
+        NaiveTime::parse_from_str(raw, "%H:%M")
+            .map(|time| Local::today().naive_local().and_time(time))
+    });
+    naive
+        .ok()
+        .and_then(|naive| Local.from_local_datetime(&naive).single())
+        .ok_or_else(|| ErrorKind::InvalidDate(raw.to_string()).into())
+}
+
+/// Formats a file size the way `ls -h` would, e.g. "512 B" or "1.5 KB".
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
     }
 }
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use issue::{Issue, IssueVec};
+
+/// Something that happened to an interrupt between two runs of a query.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    New(&'a Issue),
+    Status { issue: &'a Issue, from: String },
+    Assignee { issue: &'a Issue, from: String },
+}
+
+impl<'a> Change<'a> {
+    pub fn issue(&self) -> &'a Issue {
+        match *self {
+            Change::New(issue)
+            | Change::Status { issue, .. }
+            | Change::Assignee { issue, .. } => issue,
+        }
+    }
+
+    /// A short name for the kind of change, handed to hooks as `OB_EVENT`.
+    pub fn event(&self) -> &'static str {
+        match *self {
+            Change::New(_) => "new",
+            Change::Status { .. } => "status",
+            Change::Assignee { .. } => "assignee",
+        }
+    }
+}
+
+impl<'a> fmt::Display for Change<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::New(issue) => write!(f, "new: {} {}", issue.key, issue.summary),
+            Change::Status { issue, ref from } => write!(
+                f,
+                "{} status: {} -> {} ({})",
+                issue.key, from, issue.status, issue.summary
+            ),
+            Change::Assignee { issue, ref from } => write!(
+                f,
+                "{} assignee: {} -> {} ({})",
+                issue.key, from, issue.assignee, issue.summary
+            ),
+        }
+    }
+}
+
+/// Compares two results of the same query. Issues that dropped out of the
+/// result aren't reported, they are usually just closed.
+pub fn diff<'a>(previous: &IssueVec, current: &'a IssueVec) -> Vec<Change<'a>> {
+    let mut changes = Vec::new();
+    for issue in current.iter() {
+        match previous.iter().find(|old| old.key == issue.key) {
+            None => changes.push(Change::New(issue)),
+            Some(old) => {
+                if old.status != issue.status {
+                    changes.push(Change::Status {
+                        issue: issue,
+                        from: old.status.clone(),
+                    });
+                }
+                if old.assignee != issue.assignee {
+                    changes.push(Change::Assignee {
+                        issue: issue,
+                        from: old.assignee.clone(),
+                    });
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue(key: &str, status: &str, assignee: &str) -> Issue {
+        Issue {
+            status: status.to_string(),
+            assignee: assignee.to_string(),
+            ..Issue::example(key)
+        }
+    }
+
+    #[test]
+    fn reports_new_issues_and_changes() {
+        let previous = IssueVec::from(vec![
+            issue("FOO-1", "Open", "Foo Robot"),
+            issue("FOO-2", "Open", "Foo Robot"),
+            issue("FOO-3", "Open", "Foo Robot"),
+        ]);
+        let current = IssueVec::from(vec![
+            issue("FOO-1", "Open", "Foo Robot"),
+            issue("FOO-2", "In Progress", "Bob"),
+            issue("FOO-4", "Open", "Foo Robot"),
+        ]);
+
+        let changes = diff(&previous, &current);
+        let described: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            described,
+            vec![
+                "FOO-2 status: Open -> In Progress (summary of FOO-2)",
+                "FOO-2 assignee: Foo Robot -> Bob (summary of FOO-2)",
+                "new: FOO-4 summary of FOO-4",
+            ]
+        );
+        assert_eq!(changes[2].event(), "new");
+        assert_eq!(changes[2].issue().key, "FOO-4");
+    }
+
+    #[test]
+    fn identical_results_have_no_changes() {
+        let previous = IssueVec::from(vec![issue("FOO-1", "Open", "Foo Robot")]);
+        let current = IssueVec::from(vec![issue("FOO-1", "Open", "Foo Robot")]);
+        assert!(diff(&previous, &current).is_empty());
+    }
+}
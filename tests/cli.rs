@@ -0,0 +1,1012 @@
+extern crate chrono;
+#[macro_use]
+extern crate serde_json;
+extern crate tiny_http;
+
+mod support;
+
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use support::{stderr, stdout, TestEnv};
+use support::fake_jira::{FakeJira, StoredIssue, Store, TEAM_FIELD};
+
+#[test]
+fn list_shows_open_interrupts_in_the_configured_projects() {
+    let env = TestEnv::new();
+    let output = env.ob(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let out = stdout(&output);
+    assert!(out.contains("FOO-1"));
+    assert!(out.contains("FOO-2"));
+    assert!(out.contains("FOO-3"));
+    assert!(!out.contains("FOO-4"), "closed issues should not be listed");
+    assert!(!out.contains("BAR-1"), "other projects should not be listed");
+}
+
+#[test]
+fn current_shows_interrupts_assigned_to_me() {
+    let env = TestEnv::new();
+    let output = env.ob(&["current"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let out = stdout(&output);
+    assert!(out.contains("FOO-2"));
+    assert!(out.contains("Disk full on build-03"));
+    assert!(!out.contains("FOO-1"));
+    assert!(!out.contains("FOO-4"));
+}
+
+#[test]
+fn next_shows_unclaimed_open_interrupts() {
+    let env = TestEnv::new();
+    let output = env.ob(&["next"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let out = stdout(&output);
+    assert!(out.contains("FOO-1"), "issues assigned to a team user are up for grabs");
+    assert!(out.contains("FOO-3"), "unassigned issues are up for grabs");
+    assert!(!out.contains("FOO-2"));
+    assert!(!out.contains("BAR-1"));
+}
+
+#[test]
+fn new_files_an_issue_with_the_configured_defaults() {
+    let env = TestEnv::new();
+    let output = env.ob(&["new", "Printer on fire", "-d", "again"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let out = stdout(&output);
+    assert!(out.contains("FOO-5"));
+    assert!(out.contains("Printer on fire"));
+    assert!(out.contains("Foo Robot"));
+
+    let store = env.jira.store.lock().unwrap();
+    let issue = store.issue("FOO-5").unwrap();
+    assert_eq!(issue.description, "again");
+    assert_eq!(issue.assignee, Some("foo-robot".to_string()));
+    assert_eq!(issue.labels, vec!["interrupt".to_string()]);
+}
+
+#[test]
+fn new_reports_errors_from_jira() {
+    let env = TestEnv::new();
+    let output = env.ob(&["new", "Printer on fire", "-a", "nobody"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("User 'nobody' does not exist."));
+}
+
+#[test]
+fn jql_runs_a_raw_query() {
+    let env = TestEnv::new();
+    let output = env.ob(&["jql", "key = \"BAR-1\""]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let out = stdout(&output);
+    assert!(out.contains("BAR-1"));
+    assert!(out.contains("Someone else's problem"));
+    assert!(!out.contains("FOO-1"));
+}
+
+#[test]
+fn jql_can_show_urls() {
+    let env = TestEnv::new();
+    let output = env.ob(&["jql", "--url", "project = BAR"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains(&format!("{}browse/BAR-1", env.jira.url)));
+}
+
+#[test]
+fn jql_reports_invalid_queries() {
+    let env = TestEnv::new();
+    let output = env.ob(&["jql", "flavour = \"vanilla\""]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Field 'flavour' does not exist"));
+}
+
+#[test]
+fn issue_shows_the_details_of_an_issue() {
+    let env = TestEnv::new();
+    let output = env.ob(&["issue", "FOO-1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let out = stdout(&output);
+    assert!(out.contains("The build is broken"));
+    assert!(out.contains("Foo Robot"));
+    assert!(out.contains("Alice"));
+    assert!(out.contains("interrupt"));
+}
+
+#[test]
+fn issue_reports_missing_issues() {
+    let env = TestEnv::new();
+    let output = env.ob(&["issue", "FOO-99"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("issue FOO-99 not found"));
+}
+
+#[test]
+fn empty_results_are_not_a_failure_by_default() {
+    let env = TestEnv::new();
+    let output = env.ob(&["jql", "assignee = alice"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("No issues found"));
+}
+
+#[test]
+fn empty_results_fail_with_fail_on_empty() {
+    let env = TestEnv::new();
+    let output = env.ob(&["jql", "--fail-on-empty", "assignee = alice"]);
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn bad_credentials_have_their_own_exit_code() {
+    let env = TestEnv::new();
+    let output = env.ob(&["--set", "auth=Ym9iOndyb25n", "list"]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn config_errors_have_their_own_exit_code() {
+    let env = TestEnv::new();
+    let output = env.ob(&["--set", "open_in_browser=sometimes", "list"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("config.open_in_browser"));
+}
+
+#[test]
+fn profiles_are_picked_by_flag_over_environment() {
+    let env = TestEnv::new();
+    let config = fs::read_to_string(&env.config).unwrap().replace(
+        "profiles: {}",
+        "profiles:
+  ops:
+    new_issue_defaults:
+      project_key: BAR
+  dev:
+    new_issue_defaults:
+      assignee: alice
+",
+    );
+    fs::write(&env.config, config).unwrap();
+
+    let output = env.ob_with_env(&["new", "Printer on fire"], &[("OB_PROFILE", "ops")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("BAR-2"), "{}", stdout(&output));
+
+    let output = env.ob_with_env(
+        &["--profile", "dev", "new", "Printer still on fire"],
+        &[("OB_PROFILE", "ops")],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-5"), "{}", stdout(&output));
+    let assignee = env.jira.store.lock().unwrap().issue("FOO-5").unwrap().assignee.clone();
+    assert_eq!(assignee, Some("alice".to_string()));
+
+    let output = env.ob_with_env(&["list"], &[("OB_PROFILE", "staging")]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("no profile named 'staging'"), "{}", stderr(&output));
+}
+
+#[test]
+fn a_repository_config_only_pins_project_settings() {
+    let env = TestEnv::new();
+    fs::create_dir(env.dir.join(".git")).unwrap();
+    let config = fs::read_to_string(&env.config).unwrap().replace(
+        "profiles: {}",
+        "profiles:\n  ops:\n    new_issue_defaults:\n      project_key: FOO\n",
+    );
+    fs::write(&env.config, config).unwrap();
+
+    // the repository's file goes on top of the profile
+    let repo_config = env.dir.join(".ob.yml");
+    fs::write(&repo_config, "config:\n  new_issue_defaults:\n    project_key: BAR\n").unwrap();
+    let output = env.ob(&["--profile", "ops", "new", "Printer on fire"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("BAR-2"), "{}", stdout(&output));
+
+    fs::write(&repo_config, "config:\n  jira: \"https://jira.evil.example.com/\"\n").unwrap();
+    let output = env.ob(&["list"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains(".ob.yml: config.jira"), "{}", stderr(&output));
+
+    fs::remove_file(&env.config).unwrap();
+    let output = env.ob(&["list"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("doesn't exist"), "{}", stderr(&output));
+}
+
+#[test]
+fn a_config_in_a_home_directory_repository_is_not_a_repository_config() {
+    let mut env = TestEnv::new();
+    fs::create_dir(env.dir.join(".git")).unwrap();
+    let config = env.dir.join(".ob.yml");
+    fs::rename(&env.config, &config).unwrap();
+    env.config = config;
+
+    let output = env.ob(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn old_configs_are_only_rewritten_by_config_migrate() {
+    let env = TestEnv::new();
+    let old = fs::read_to_string(&env.config)
+        .unwrap()
+        .replace("config_version: 2\n", "")
+        .replace("profiles: {}\n", "");
+    fs::write(&env.config, &old).unwrap();
+
+    let output = env.ob(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("run 'ob config migrate'"), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(&env.config).unwrap(), old);
+
+    let output = env.ob(&["config", "migrate"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fs::read_to_string(&env.config).unwrap().contains("config_version: 2"));
+    assert_eq!(fs::read_to_string(env.dir.join("ob.yml.v1.bak")).unwrap(), old);
+
+    let output = env.ob(&["config", "migrate"]);
+    assert!(stdout(&output).contains("already up to date"), "{}", stdout(&output));
+    let output = env.ob(&["list"]);
+    assert!(!stderr(&output).contains("warning"), "{}", stderr(&output));
+}
+
+#[test]
+fn verbose_logs_requests_to_stderr_only() {
+    let env = TestEnv::new();
+    let output = env.ob(&["-v", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let err = stderr(&output);
+    assert!(err.contains("POST"));
+    assert!(err.contains("rest/api/2/search -> 200"));
+    assert!(!stdout(&output).contains("rest/api/2/search"));
+}
+
+#[test]
+fn trace_file_captures_redacted_requests() {
+    let env = TestEnv::new();
+    let trace = env.dir.join("trace.log");
+    let output = env.ob(&["--trace-file", trace.to_str().unwrap(), "issue", "FOO-1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let mut contents = String::new();
+    File::open(&trace)
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert!(contents.contains("rest/api/2/issue/FOO-1"));
+    assert!(contents.contains("The build is broken"));
+    assert!(!contents.contains("hunter2"));
+    assert!(!contents.contains("fake-session"));
+    assert!(stderr(&output).is_empty(), "tracing shouldn't be noisy");
+}
+
+#[test]
+fn offline_shows_the_last_cached_results() {
+    let env = TestEnv::new();
+    let output = env.ob(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    env.jira.store.lock().unwrap().issue_mut("FOO-3").unwrap().status = "Closed".to_string();
+
+    let output = env.ob(&["--offline", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-3"), "offline results are not refreshed");
+    assert!(stderr(&output).contains("cached at"));
+}
+
+#[test]
+fn profiles_for_other_instances_have_their_own_cache() {
+    let env = TestEnv::new();
+    let mut store = Store::default();
+    store.add(StoredIssue::new("FOO-1", "Cloud is on fire", "Open"));
+    let cloud = FakeJira::start(store);
+    let config = fs::read_to_string(&env.config).unwrap().replace(
+        "profiles: {}",
+        &format!("profiles:\n  cloud:\n    jira: \"{}\"\n", cloud.url),
+    );
+    fs::write(&env.config, config).unwrap();
+
+    for args in &[&["issue", "FOO-1"][..], &["--profile", "cloud", "issue", "FOO-1"][..]] {
+        let output = env.ob(args);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    let output = env.ob(&["--offline", "issue", "FOO-1"]);
+    assert!(stdout(&output).contains("The build is broken"), "{}", stdout(&output));
+    let output = env.ob(&["--profile", "cloud", "--offline", "issue", "FOO-1"]);
+    assert!(stdout(&output).contains("Cloud is on fire"), "{}", stdout(&output));
+}
+
+#[test]
+fn offline_without_a_cache_is_an_error() {
+    let env = TestEnv::new();
+    let output = env.ob(&["--offline", "issue", "FOO-1"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("not in the offline cache"));
+}
+
+#[test]
+fn cached_queries_pick_up_changes_incrementally() {
+    let env = TestEnv::new();
+    assert!(env.ob(&["list"]).status.success());
+
+    {
+        let mut store = env.jira.store.lock().unwrap();
+        store.issue_mut("FOO-3").unwrap().status = "Closed".to_string();
+        store.issue_mut("FOO-2").unwrap().summary = "Disk full on build-04".to_string();
+        store.add(StoredIssue::new("FOO-5", "Pager is on fire", "Open"));
+    }
+
+    let output = env.ob(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("FOO-1"));
+    assert!(out.contains("build-04"));
+    assert!(out.contains("FOO-5"));
+    assert!(!out.contains("FOO-3"), "issues that stopped matching are dropped");
+
+    let output = env.ob(&["--refresh", "list"]);
+    assert_eq!(stdout(&output), out);
+
+    // jira rejects the incremental query once a cached issue is deleted
+    env.jira.store.lock().unwrap().remove("FOO-1");
+    let output = env.ob(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(!out.contains("FOO-1"), "{}", out);
+    assert!(out.contains("FOO-5"), "{}", out);
+}
+
+#[test]
+fn watch_reports_changes_and_runs_the_hook() {
+    let env = TestEnv::new();
+    let store = env.jira.store.clone();
+    let changer = thread::spawn(move || {
+        // after the first poll, however long ob takes to get there
+        for _ in 0..1000 {
+            if store.lock().unwrap().searches > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        store
+            .lock()
+            .unwrap()
+            .add(StoredIssue::new("FOO-5", "Pager is on fire", "Open"));
+    });
+
+    let output = env.ob(&[
+        "watch",
+        "--interval",
+        "1",
+        "--count",
+        "2",
+        "--hook",
+        "cat > \"hook-$OB_EVENT.json\"",
+    ]);
+    changer.join().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Watching 2 issue(s)"));
+    assert!(stdout(&output).contains("new: FOO-5 Pager is on fire"));
+
+    let mut hook = String::new();
+    File::open(env.dir.join("hook-new.json"))
+        .unwrap()
+        .read_to_string(&mut hook)
+        .unwrap();
+    let issue: serde_json::Value = serde_json::from_str(&hook).unwrap();
+    assert_eq!(issue["key"], "FOO-5");
+
+    let output = env.ob(&["watch", "--interval", "1", "--count", "twice"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--count must be a whole number, got 'twice'"));
+}
+
+#[test]
+fn a_hook_that_exits_without_reading_the_issue_reports_its_status() {
+    let env = TestEnv::new();
+    let store = env.jira.store.clone();
+    let changer = thread::spawn(move || {
+        // after the first poll, however long ob takes to get there
+        for _ in 0..1000 {
+            if store.lock().unwrap().searches > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        store
+            .lock()
+            .unwrap()
+            .add(StoredIssue::new("FOO-5", "Pager is on fire", "Open"));
+    });
+
+    let output = env.ob(&["watch", "--interval", "1", "--count", "2", "--hook", "exit 3"]);
+    changer.join().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let err = stderr(&output);
+    assert!(err.contains("hook 'exit 3' failed: exit status: 3"), "{}", err);
+    assert!(!err.contains("could not"), "{}", err);
+}
+
+#[test]
+fn logged_time_shows_up_on_the_issue_and_in_the_report() {
+    let env = TestEnv::new();
+    let output = env.ob(&["log", "FOO-1", "1h30m", "-m", "rebooted the agents"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Logged 1h 30m on FOO-1"));
+
+    {
+        let store = env.jira.store.lock().unwrap();
+        let worklog = &store.issue("FOO-1").unwrap().worklogs[0];
+        assert_eq!(worklog["timeSpentSeconds"], 5400);
+        assert_eq!(worklog["comment"], "rebooted the agents");
+    }
+
+    let output = env.ob(&["issue", "FOO-1", "--worklogs"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("rebooted the agents"));
+    assert!(out.contains("Total: 1h 30m"));
+
+    assert!(env.ob(&["log", "FOO-2", "2d"]).status.success());
+    let output = env.ob(&["report", "time", "--since", "1w"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("FOO-1"));
+    assert!(out.contains("FOO-2"));
+    assert!(out.contains("Total: 2d 1h 30m"));
+}
+
+#[test]
+fn invalid_durations_are_rejected() {
+    let env = TestEnv::new();
+    let output = env.ob(&["log", "FOO-1", "90"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("is not a duration"));
+}
+
+fn write_timer(env: &TestEnv, issue: &str, minutes_ago: i64) {
+    let dir = env.dir.join(".local").join("state").join("ob");
+    fs::create_dir_all(&dir).unwrap();
+    let started = chrono::Utc::now() - chrono::Duration::minutes(minutes_ago);
+    let state = json!({ "timer": { "issue": issue, "started_at": started } });
+    fs::write(dir.join("state.json"), state.to_string()).unwrap();
+}
+
+#[test]
+fn start_moves_the_issue_and_starts_a_timer() {
+    let env = TestEnv::new();
+    let output = env.ob(&["start", "FOO-1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-1 is In Progress, timer started"));
+    assert_eq!(env.jira.store.lock().unwrap().issue("FOO-1").unwrap().status, "In Progress");
+
+    let output = env.ob(&["status"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-1: The build is broken (In Progress) for 0m"));
+}
+
+#[test]
+fn stop_offers_the_timed_work_as_a_worklog() {
+    let env = TestEnv::new();
+    write_timer(&env, "FOO-2", 90);
+
+    let output = env.ob(&["stop", "--log"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("FOO-2 is Open"));
+    assert!(out.contains("Logged 1h 30m on FOO-2"));
+
+    let store = env.jira.store.lock().unwrap();
+    assert_eq!(store.issue("FOO-2").unwrap().worklogs[0]["timeSpentSeconds"], 5400);
+    drop(store);
+
+    let output = env.ob(&["status"]);
+    assert!(stdout(&output).contains("No interrupt in progress"));
+}
+
+#[test]
+fn close_without_an_answer_does_not_log_work() {
+    let env = TestEnv::new();
+    write_timer(&env, "FOO-1", 30);
+
+    // stdin is closed, so the question is answered with no
+    let output = env.ob(&["close"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-1 is Closed"));
+
+    let store = env.jira.store.lock().unwrap();
+    assert!(store.issue("FOO-1").unwrap().worklogs.is_empty());
+}
+
+#[test]
+fn stop_without_a_timer_needs_an_issue() {
+    let env = TestEnv::new();
+    let output = env.ob(&["stop"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no interrupt is in progress"));
+}
+
+#[test]
+fn commands_default_to_the_active_issue() {
+    let env = TestEnv::new();
+    assert!(env.ob(&["start", "FOO-3"]).status.success());
+
+    let output = env.ob(&["comment", "looking into it"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Commented on FOO-3"));
+
+    let output = env.ob(&["log", "20m"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Logged 20m on FOO-3"));
+
+    let output = env.ob(&["issue"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Rotate the TLS certs"));
+
+    {
+        let store = env.jira.store.lock().unwrap();
+        let issue = store.issue("FOO-3").unwrap();
+        assert_eq!(issue.comments[0]["body"], "looking into it");
+        assert_eq!(issue.worklogs[0]["timeSpentSeconds"], 1200);
+    }
+
+    let output = env.ob(&["close", "--no-log"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-3 is Closed"));
+
+    let output = env.ob(&["comment", "anyone?"]);
+    assert!(stderr(&output).contains("no interrupt is in progress"));
+}
+
+#[test]
+fn switch_changes_the_active_issue_and_status_shows_history() {
+    let env = TestEnv::new();
+    assert!(env.ob(&["start", "FOO-1"]).status.success());
+    assert!(env.ob(&["issue", "FOO-2"]).status.success());
+
+    let output = env.ob(&["switch", "FOO-2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Switched to FOO-2: Disk full on build-03 (In Progress)"));
+    assert!(out.contains("The timer is still running on FOO-1"));
+
+    let output = env.ob(&["status"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("FOO-2: Disk full on build-03 (In Progress)"));
+    assert!(out.contains("The timer is running on FOO-1"));
+    assert!(out.contains("Recent: FOO-1"));
+}
+
+fn git(env: &TestEnv, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(&["-c", "user.name=Bob", "-c", "user.email=bob@example.com"])
+        .args(args)
+        .current_dir(&env.dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, stderr(&output));
+    stdout(&output)
+}
+
+#[test]
+fn branch_is_named_after_the_issue_and_commands_read_it_back() {
+    let env = TestEnv::new();
+    git(&env, &["init", "-q"]);
+
+    let output = env.ob(&["branch", "foo-3"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Switched to a new branch 'FOO-3-rotate-the-tls-certs'"));
+    assert_eq!(
+        git(&env, &["symbolic-ref", "--short", "HEAD"]).trim(),
+        "FOO-3-rotate-the-tls-certs"
+    );
+
+    // ob issue prefers the branch over the active issue
+    assert!(env.ob(&["switch", "FOO-1"]).status.success());
+    let output = env.ob(&["issue"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Rotate the TLS certs"));
+
+    // everything else keeps acting on the active issue
+    let output = env.ob(&["comment", "looking into it"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let store = env.jira.store.lock().unwrap();
+    assert_eq!(store.issue("FOO-1").unwrap().comments.len(), 1);
+    assert!(store.issue("FOO-3").unwrap().comments.is_empty());
+}
+
+#[test]
+fn installed_hook_adds_the_issue_to_commit_messages() {
+    let env = TestEnv::new();
+    git(&env, &["init", "-q"]);
+    git(&env, &["checkout", "-q", "-b", "FOO-2-disk-full"]);
+
+    let output = env.ob(&["hook", "install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("prepare-commit-msg"));
+
+    git(&env, &["commit", "-q", "--allow-empty", "-m", "Clean up old builds"]);
+    let message = git(&env, &["log", "-1", "--format=%B"]);
+    assert!(message.contains("Clean up old builds"));
+    assert!(message.contains("Issue: FOO-2"), "{}", message);
+
+    // a hook from somewhere else is left alone
+    let hook = env.dir.join(".git").join("hooks").join("prepare-commit-msg");
+    fs::write(&hook, "#!/bin/sh\n").unwrap();
+    let output = env.ob(&["hook", "install"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--force"));
+    assert!(env.ob(&["hook", "install", "--force"]).status.success());
+}
+
+#[test]
+fn links_to_urls_and_issues_show_up_on_the_issue() {
+    let env = TestEnv::new();
+
+    let output = env.ob(&[
+        "link",
+        "FOO-1",
+        "https://git.example.com/pr/7",
+        "--title",
+        "Fix the build",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Linked FOO-1 to https://git.example.com/pr/7"));
+
+    let output = env.ob(&["link", "FOO-1", "foo-3", "--type", "blocks"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("FOO-1 blocks FOO-3"));
+
+    let out = stdout(&env.ob(&["issue", "FOO-1"]));
+    assert!(out.contains("links to https://git.example.com/pr/7 (Fix the build)"), "{}", out);
+    assert!(out.contains("blocks FOO-3 (Rotate the TLS certs)"), "{}", out);
+
+    let out = stdout(&env.ob(&["issue", "FOO-3"]));
+    assert!(out.contains("is blocked by FOO-1 (The build is broken)"), "{}", out);
+
+    let output = env.ob(&["link", "FOO-1", "https://example.com", "--type", "blocks"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--type only applies to links between issues"));
+}
+
+#[test]
+fn subtasks_and_epics_show_up_on_the_issue() {
+    let env = TestEnv::new();
+
+    let output = env.ob(&["new", "Rebuild the agents", "--parent", "foo-1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("FOO-1 The build is broken (Open)"), "{}", out);
+
+    let output = env.ob(&["new", "Write a postmortem", "--epic", "FOO-3"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    {
+        let store = env.jira.store.lock().unwrap();
+        let subtask = store.issue("FOO-5").unwrap();
+        assert_eq!(subtask.parent, Some("FOO-1".to_string()));
+        assert_eq!(subtask.issuetype, "Sub-task");
+        assert_eq!(store.issue("FOO-6").unwrap().epic, Some("FOO-3".to_string()));
+    }
+
+    let out = stdout(&env.ob(&["issue", "FOO-1"]));
+    assert!(out.contains("Subtasks"), "{}", out);
+    assert!(out.contains("FOO-5 Rebuild the agents (Open)"), "{}", out);
+
+    let out = stdout(&env.ob(&["issue", "FOO-6"]));
+    assert!(out.contains("Epic"), "{}", out);
+    assert!(out.contains("FOO-3"), "{}", out);
+
+    let output = env.ob(&["new", "Orphan", "--parent", "FOO-99"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("parent: Could not find issue"));
+}
+
+#[test]
+fn searches_keep_the_details_of_cached_issues() {
+    let env = TestEnv::new();
+    env.jira.store.lock().unwrap().issue_mut("FOO-1").unwrap().epic = Some("FOO-3".to_string());
+    assert!(env.ob(&["issue", "FOO-1"]).status.success());
+    assert!(env.ob(&["list"]).status.success());
+
+    let output = env.ob(&["--offline", "issue", "FOO-1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Epic"), "{}", stdout(&output));
+}
+
+#[test]
+fn issues_are_created_from_files_and_stdin() {
+    let env = TestEnv::new();
+
+    let alert = "---
+labels: [disk, build]
+assignee: bob
+fields:
+  issuetype: {name: Task}
+---
+
+# Disk full on build-04
+
+/var is at 100%.
+";
+    let output = env.ob_with_input(&["new", "--stdin"], alert);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Disk full on build-04"));
+
+    fs::write(
+        env.dir.join("alert.yml"),
+        "summary: Rotate the keys\nproject: BAR\nlabels: [security]\n",
+    ).unwrap();
+    let output = env.ob(&["new", "--from-file", "alert.yml", "--label", "urgent"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    {
+        let store = env.jira.store.lock().unwrap();
+        let issue = store.issue("FOO-5").unwrap();
+        assert_eq!(issue.summary, "Disk full on build-04");
+        assert_eq!(issue.description, "/var is at 100%.");
+        assert_eq!(issue.labels, vec!["disk", "build"]);
+        assert_eq!(issue.assignee, Some("bob".to_string()));
+        assert_eq!(issue.issuetype, "Task");
+
+        // the command line wins over the file
+        let issue = store.issue("BAR-2").unwrap();
+        assert_eq!(issue.summary, "Rotate the keys");
+        assert_eq!(issue.labels, vec!["urgent"]);
+    }
+
+    let output = env.ob_with_input(&["new", "--stdin"], "summary: Renew the certs\nproject: BAR\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(env.jira.store.lock().unwrap().issue("BAR-3").unwrap().summary, "Renew the certs");
+
+    let output = env.ob_with_input(&["new", "--stdin"], "just a description\n");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("use --format"));
+
+    let output = env.ob_with_input(&["new", "--stdin", "--format", "md"], "just a description\n");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid issue file: there's no summary"));
+
+    let output = env.ob_with_input(
+        &["new", "--stdin", "--epic", "FOO-2"],
+        "summary: Rotate more keys\nparent: FOO-1\n",
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("a parent or an epic, not both"));
+
+    let output = env.ob_with_input(&["new", "--stdin", "--format", "yml"], "sumary: typo\n");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid issue file"));
+}
+
+#[test]
+fn import_checks_every_row_before_creating_issues() {
+    let env = TestEnv::new();
+    let alias = format!("field_aliases.team={}", TEAM_FIELD);
+
+    fs::write(
+        env.dir.join("review.csv"),
+        "summary,type,team,labels,assignee
+Review access for alice,Task,Sales,\"access-review, q3\",alice
+Review access for carol,Task,,access-review,carol
+",
+    ).unwrap();
+    let output = env.ob(&["--set", &alias, "import", "review.csv", "--dry-run"]);
+    assert!(!output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("row 2: team: 'Sales' isn't one of Platform, Security"), "{}", out);
+    assert!(out.contains("row 3: Team is required"), "{}", out);
+    assert!(out.contains("0 of 2 rows can be imported"), "{}", out);
+    assert!(stderr(&output).contains("2 of 2 rows could not be imported"));
+
+    let output = env.ob(&["--set", &alias, "import", "review.csv"]);
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Nothing was imported"));
+    assert!(env.jira.store.lock().unwrap().issue("FOO-5").is_none());
+
+    fs::write(
+        env.dir.join("review.csv"),
+        "summary,type,team,labels,assignee
+Review access for alice,Task,security,\"access-review, q3\",alice
+Review access for carol,Task,Platform,access-review,carol
+Review access for dave,Bug,,,dave
+",
+    ).unwrap();
+    let output = env.ob(&["--set", &alias, "import", "review.csv", "--dry-run"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("row 2: ok, a Task in FOO: Review access for alice"));
+    assert!(env.jira.store.lock().unwrap().issue("FOO-5").is_none());
+
+    // jira still has the last word, on users for example
+    let output = env.ob(&["--set", &alias, "import", "review.csv"]);
+    assert!(!output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("row 2: created FOO-5 Review access for alice"), "{}", out);
+    assert!(out.contains("row 3: created FOO-6 Review access for carol"), "{}", out);
+    assert!(out.contains("row 4: assignee: User 'dave' does not exist."), "{}", out);
+    assert!(out.contains("Created 2 of 3 issues"), "{}", out);
+
+    let store = env.jira.store.lock().unwrap();
+    let issue = store.issue("FOO-5").unwrap();
+    assert_eq!(issue.issuetype, "Task");
+    assert_eq!(issue.assignee, Some("alice".to_string()));
+    assert_eq!(issue.labels, vec!["access-review", "q3"]);
+    assert_eq!(issue.custom_fields[TEAM_FIELD]["value"], "Security");
+    assert!(store.issue("FOO-7").is_none());
+}
+
+#[test]
+fn bulk_changes_every_issue_after_asking() {
+    let env = TestEnv::new();
+    let query = "project = FOO AND status = Open";
+
+    let output = env.ob_with_input(&["bulk", query, "--add-label", "stale"], "n\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Rotate the TLS certs"), "{}", out);
+    assert!(out.contains("Change 2 issue(s): add the label stale?"), "{}", out);
+    assert!(out.contains("Nothing was changed"), "{}", out);
+    assert_eq!(env.jira.store.lock().unwrap().issue("FOO-1").unwrap().labels, vec!["interrupt"]);
+
+    let output = env.ob(&[
+        "bulk",
+        query,
+        "--assign",
+        "carol",
+        "--add-label",
+        "stale",
+        "-m",
+        "cleaning up",
+        "--transition",
+        "Closed",
+        "--yes",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("ok    FOO-1"), "{}", out);
+    assert!(out.contains("ok    FOO-3"), "{}", out);
+    assert!(out.contains("Changed 2 of 2 issues"), "{}", out);
+    {
+        let store = env.jira.store.lock().unwrap();
+        for key in &["FOO-1", "FOO-3"] {
+            let issue = store.issue(key).unwrap();
+            assert_eq!(issue.status, "Closed");
+            assert_eq!(issue.assignee, Some("carol".to_string()));
+            assert!(issue.labels.contains(&"stale".to_string()));
+            assert_eq!(issue.comments.len(), 1);
+        }
+        assert_eq!(store.issue("FOO-2").unwrap().status, "In Progress");
+    }
+
+    let output = env.ob(&["bulk", "key = FOO-2", "--transition", "Done", "--yes"]);
+    assert!(!output.status.success());
+    let out = stdout(&output);
+    assert!(
+        out.contains("FAIL  FOO-2: move to Done: FOO-2 can't be moved to Done"),
+        "{}",
+        out
+    );
+    assert!(stderr(&output).contains("1 of 1 issues could not be changed"));
+
+    let output = env.ob(&["bulk", query]);
+    assert!(!output.status.success());
+
+    let output = env.ob(&["bulk", query, "--add-label", "stale", "--concurrency", "many"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--concurrency must be a whole number, got 'many'"));
+}
+
+#[test]
+fn bulk_pages_through_every_matching_issue() {
+    let env = TestEnv::new();
+    env.jira.store.lock().unwrap().page_limit = Some(1);
+
+    // oldest first, across pages as well as within them
+    let output = env.ob_with_input(&["bulk", "project = FOO", "--add-label", "stale"], "n\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Change 4 issue(s)"), "{}", out);
+    let positions: Vec<usize> = ["FOO-1", "FOO-2", "FOO-3", "FOO-4"]
+        .iter()
+        .map(|key| out.find(key).unwrap())
+        .collect();
+    let mut sorted = positions.clone();
+    sorted.sort();
+    assert_eq!(positions, sorted, "{}", out);
+
+    let output = env.ob(&["bulk", "project = FOO AND status = Open", "--add-label", "stale", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Changed 2 of 2 issues"), "{}", out);
+    let store = env.jira.store.lock().unwrap();
+    for key in &["FOO-1", "FOO-3"] {
+        assert!(store.issue(key).unwrap().labels.contains(&"stale".to_string()));
+    }
+}
+
+#[test]
+fn labels_are_added_removed_and_counted() {
+    let env = TestEnv::new();
+
+    let output = env.ob(&["label", "FOO-1", "+stale", "-interrupt"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Labels on FOO-1: stale"), "{}", stdout(&output));
+    assert_eq!(env.jira.store.lock().unwrap().issue("FOO-1").unwrap().labels, vec!["stale"]);
+
+    let output = env.ob(&["label", "FOO-3", "-missing", "+stale", "+docs"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Labels on FOO-3: stale, docs"), "{}", stdout(&output));
+
+    let output = env.ob(&["label", "FOO-3", "docs"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("'docs' should be +label"), "{}", stderr(&output));
+
+    // the active issue, with -- for a removal that looks like an option
+    assert!(env.ob(&["switch", "FOO-3"]).status.success());
+    let output = env.ob(&["label", "+v"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Labels on FOO-3: stale, docs, v"), "{}", stdout(&output));
+    let output = env.ob(&["label", "--", "-v"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Labels on FOO-3: stale, docs\n"), "{}", stdout(&output));
+
+    let output = env.ob(&["labels"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    let stale = out.find("stale").expect(&out);
+    let docs = out.find("docs").expect(&out);
+    assert!(stale < docs, "{}", out);
+    assert!(!out.contains("interrupt"), "{}", out);
+
+    let output = env.ob(&["labels", "--project", "BAR"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("No labels in use"), "{}", stdout(&output));
+}
+
+#[test]
+fn attachments_are_uploaded_listed_and_downloaded() {
+    let env = TestEnv::new();
+    let logs = env.dir.join("logs");
+    fs::create_dir_all(&logs).unwrap();
+    fs::write(logs.join("build.log"), "error: disk full\n").unwrap();
+    let screenshot: Vec<u8> = (0..=255).collect();
+    fs::write(logs.join("screen.png"), &screenshot).unwrap();
+
+    let output = env.ob(&["attach", "FOO-2", "logs/build.log", "logs/screen.png"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Attached build.log (17 B) to FOO-2"), "{}", out);
+    assert!(out.contains("Attached screen.png (256 B) to FOO-2"), "{}", out);
+
+    let out = stdout(&env.ob(&["issue", "FOO-2"]));
+    assert!(out.contains("Attachments (2)"), "{}", out);
+    assert!(out.contains("screen.png"), "{}", out);
+
+    let output = env.ob(&["attachments", "FOO-2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("build.log"));
+
+    let output = env.ob(&["attachments", "FOO-2", "--download", "--dir", "saved"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let saved = env.dir.join("saved");
+    assert_eq!(
+        fs::read_to_string(saved.join("build.log")).unwrap(),
+        "error: disk full\n"
+    );
+    assert_eq!(fs::read(saved.join("screen.png")).unwrap(), screenshot);
+
+    let output = env.ob(&["attachments", "FOO-2", "--download", "--dir", "saved"]);
+    assert!(stdout(&output).contains("already exists"));
+}
@@ -0,0 +1,1175 @@
+//! A small in-memory stand-in for the parts of the Jira REST api that `ob`
+//! talks to. It speaks just enough JQL to answer the queries `ob` generates.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::DateTime;
+use serde_json;
+use serde_json::Value;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const USERNAME: &str = "bob";
+/// Where the fake keeps an issue's epic, the default of `epic_link_field`.
+pub const EPIC_LINK_FIELD: &str = "customfield_10008";
+pub const PASSWORD: &str = "hunter2";
+const SESSION: &str = "JSESSIONID=fake-session";
+
+#[derive(Clone, Debug)]
+pub struct StoredIssue {
+    pub id: u32,
+    pub key: String,
+    pub project: String,
+    pub issuetype: String,
+    pub summary: String,
+    pub description: String,
+    pub status: String,
+    pub assignee: Option<String>,
+    pub reporter: String,
+    pub labels: Vec<String>,
+    pub comments: Vec<Value>,
+    pub worklogs: Vec<Value>,
+    pub remote_links: Vec<Value>,
+    pub parent: Option<String>,
+    pub epic: Option<String>,
+    pub attachments: Vec<StoredAttachment>,
+    /// Custom fields other than the epic, as they were posted.
+    pub custom_fields: BTreeMap<String, Value>,
+    /// seconds since the epoch, bumped whenever the server changes the issue
+    pub updated: u64,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl StoredIssue {
+    pub fn new(key: &str, summary: &str, status: &str) -> StoredIssue {
+        StoredIssue {
+            id: 0,
+            key: key.to_string(),
+            project: key.split('-').next().unwrap().to_string(),
+            issuetype: "Bug".to_string(),
+            summary: summary.to_string(),
+            description: String::new(),
+            status: status.to_string(),
+            assignee: None,
+            reporter: "alice".to_string(),
+            labels: Vec::new(),
+            comments: Vec::new(),
+            worklogs: Vec::new(),
+            remote_links: Vec::new(),
+            parent: None,
+            epic: None,
+            attachments: Vec::new(),
+            custom_fields: BTreeMap::new(),
+            updated: now(),
+        }
+    }
+
+    pub fn assignee(mut self, assignee: &str) -> StoredIssue {
+        self.assignee = Some(assignee.to_string());
+        self
+    }
+
+    pub fn reporter(mut self, reporter: &str) -> StoredIssue {
+        self.reporter = reporter.to_string();
+        self
+    }
+
+    pub fn labels(mut self, labels: &[&str]) -> StoredIssue {
+        self.labels = labels.iter().map(|l| l.to_string()).collect();
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredAttachment {
+    pub id: u32,
+    pub filename: String,
+    pub contents: Vec<u8>,
+}
+
+/// A link between two issues: the type's name, then the inward and outward
+/// issue as posted to `issueLink`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredLink {
+    pub link_type: String,
+    pub inward: String,
+    pub outward: String,
+}
+
+#[derive(Default)]
+pub struct Store {
+    pub issues: Vec<StoredIssue>,
+    pub links: Vec<StoredLink>,
+    pub users: BTreeMap<String, String>,
+    pub projects: Vec<String>,
+    /// Caps how many issues a search sends back at once, to exercise paging.
+    pub page_limit: Option<usize>,
+    /// How many searches have been served, to wait for a poll.
+    pub searches: usize,
+}
+
+impl Store {
+    pub fn add(&mut self, mut issue: StoredIssue) {
+        issue.id = 10000 + self.issues.len() as u32 + 1;
+        self.issues.push(issue);
+    }
+
+    pub fn issue(&self, key: &str) -> Option<&StoredIssue> {
+        self.issues.iter().find(|issue| issue.key == key)
+    }
+
+    /// Deletes an issue, as far as searches are concerned.
+    pub fn remove(&mut self, key: &str) {
+        self.issues.retain(|issue| issue.key != key);
+    }
+
+    /// The issue with the given key, marked as updated just now.
+    pub fn issue_mut(&mut self, key: &str) -> Option<&mut StoredIssue> {
+        self.issues.iter_mut().find(|issue| issue.key == key).map(|issue| {
+            issue.updated = now();
+            issue
+        })
+    }
+
+    fn next_key(&self, project: &str) -> String {
+        let count = self.issues
+            .iter()
+            .filter(|issue| issue.project == project)
+            .count();
+        format!("{}-{}", project, count + 1)
+    }
+
+    fn user_json(&self, base: &str, name: &str) -> Value {
+        json!({
+            "self": format!("{}rest/api/2/user?username={}", base, name),
+            "name": name,
+            "key": name,
+            "displayName": self.users.get(name).cloned().unwrap_or_else(|| name.to_string()),
+            "active": true,
+        })
+    }
+
+    // how an issue's links look from its side: the other issue of each link
+    fn links_json(&self, issue: &StoredIssue) -> Vec<Value> {
+        let mut links = Vec::new();
+        for link in &self.links {
+            let &(_, inward, outward) = LINK_TYPES
+                .iter()
+                .find(|&&(name, _, _)| name == link.link_type)
+                .unwrap();
+            let link_type = json!({ "name": link.link_type, "inward": inward, "outward": outward });
+            let (side, other) = if link.inward == issue.key {
+                ("outwardIssue", &link.outward)
+            } else if link.outward == issue.key {
+                ("inwardIssue", &link.inward)
+            } else {
+                continue;
+            };
+            let summary = self.issue(other).map_or("", |other| other.summary.as_str());
+            let mut json = json!({ "type": link_type });
+            json[side] = json!({ "key": other, "fields": { "summary": summary } });
+            links.push(json);
+        }
+        links
+    }
+
+    fn attachment_json(&self, base: &str, attachment: &StoredAttachment) -> Value {
+        let content = format!(
+            "{}secure/attachment/{}/{}",
+            base, attachment.id, attachment.filename
+        );
+        json!({
+            "id": attachment.id.to_string(),
+            "filename": attachment.filename,
+            "size": attachment.contents.len(),
+            "created": "2017-12-07T09:00:00.000+0000",
+            "author": self.user_json(base, USERNAME),
+            "content": content,
+        })
+    }
+
+    // another issue as embedded in an issue, e.g. its parent or a subtask
+    fn embedded_json(&self, issue: &StoredIssue) -> Value {
+        json!({
+            "key": issue.key,
+            "fields": { "summary": issue.summary, "status": { "name": issue.status } },
+        })
+    }
+
+    fn issue_json(&self, base: &str, issue: &StoredIssue) -> Value {
+        let parent = issue
+            .parent
+            .as_ref()
+            .and_then(|key| self.issue(key))
+            .map_or(Value::Null, |parent| self.embedded_json(parent));
+        let subtasks: Vec<Value> = self.issues
+            .iter()
+            .filter(|other| other.parent.as_ref() == Some(&issue.key))
+            .map(|subtask| self.embedded_json(subtask))
+            .collect();
+        let mut json = json!({
+            "id": issue.id.to_string(),
+            "self": format!("{}rest/api/2/issue/{}", base, issue.id),
+            "key": issue.key,
+            "fields": {
+                "summary": issue.summary,
+                "description": issue.description,
+                "issuetype": { "name": issue.issuetype },
+                "project": { "key": issue.project },
+                "labels": issue.labels,
+                "assignee": match issue.assignee {
+                    Some(ref name) => self.user_json(base, name),
+                    None => Value::Null,
+                },
+                "reporter": self.user_json(base, &issue.reporter),
+                "status": { "name": issue.status },
+                "issuelinks": self.links_json(issue),
+                "subtasks": subtasks,
+                "attachment": issue
+                    .attachments
+                    .iter()
+                    .map(|attachment| self.attachment_json(base, attachment))
+                    .collect::<Vec<Value>>(),
+            },
+        });
+        json["fields"][EPIC_LINK_FIELD] = json!(issue.epic);
+        // jira leaves the field out entirely for issues that aren't subtasks
+        if !parent.is_null() {
+            json["fields"]["parent"] = parent;
+        }
+        json
+    }
+}
+
+const TRANSITIONS: &[(&str, &str, &str)] = &[
+    ("11", "Start Progress", "In Progress"),
+    ("21", "Stop Progress", "Open"),
+    ("31", "Resolve Issue", "Resolved"),
+    ("41", "Close Issue", "Closed"),
+    ("51", "Reopen Issue", "Open"),
+];
+
+const LINK_TYPES: &[(&str, &str, &str)] = &[
+    ("Blocks", "is blocked by", "blocks"),
+    ("Relates", "relates to", "relates to"),
+    ("Duplicate", "is duplicated by", "duplicates"),
+];
+
+pub struct FakeJira {
+    pub url: String,
+    pub store: Arc<Mutex<Store>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl FakeJira {
+    pub fn start(store: Store) -> FakeJira {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr());
+        let store = Arc::new(Mutex::new(store));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        {
+            let store = store.clone();
+            let url = url.clone();
+            let stopped = stopped.clone();
+            // polls so that the server goes away with the test that started it
+            thread::spawn(move || while !stopped.load(Ordering::SeqCst) {
+                if let Ok(Some(request)) = server.recv_timeout(Duration::from_millis(50)) {
+                    handle(&url, &store, request);
+                }
+            });
+        }
+
+        FakeJira {
+            url: url,
+            store: store,
+            stopped: stopped,
+        }
+    }
+}
+
+impl Drop for FakeJira {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+fn respond(request: Request, status: u16, body: &Value) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    let _ = request.respond(response);
+}
+
+fn error(request: Request, status: u16, message: &str) {
+    respond(
+        request,
+        status,
+        &json!({ "errorMessages": [message], "errors": {} }),
+    );
+}
+
+fn handle(base: &str, store: &Mutex<Store>, mut request: Request) {
+    let mut raw = Vec::new();
+    let _ = request.as_reader().read_to_end(&mut raw);
+    let body: Value = serde_json::from_slice(&raw).unwrap_or(Value::Null);
+
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap().to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    if path == "/rest/auth/1/session" && method == Method::Post {
+        if body["username"] == USERNAME && body["password"] == PASSWORD {
+            let mut parts = SESSION.splitn(2, '=');
+            let session = json!({
+                "session": { "name": parts.next(), "value": parts.next() },
+            });
+            return respond(request, 200, &session);
+        }
+        return error(request, 401, "Login failed");
+    }
+
+    let authed = request.headers().iter().any(|header| {
+        header.field.equiv("Cookie") && header.value.as_str().contains(SESSION)
+    });
+    if !authed {
+        return error(request, 401, "You are not authenticated");
+    }
+
+    let mut store = store.lock().unwrap();
+    match (method, &segments[..]) {
+        (Method::Get, ["rest", "api", "2", "myself"]) => {
+            let me = store.user_json(base, USERNAME);
+            respond(request, 200, &me)
+        }
+        (Method::Post, ["rest", "api", "2", "search"]) => {
+            store.searches += 1;
+            let jql = body["jql"].as_str().unwrap_or("");
+            if let Some(key) = missing_key(jql, &store) {
+                let message = format!("An issue with key '{}' does not exist for field 'key'.", key);
+                return error(request, 400, &message);
+            }
+            let with_worklogs = body["fields"]
+                .as_array()
+                .map_or(false, |fields| fields.contains(&json!("worklog")));
+            let mut issues = Vec::new();
+            for issue in store.issues.iter().rev() {
+                match jql_matches(jql, issue) {
+                    Ok(true) => {
+                        let mut json = store.issue_json(base, issue);
+                        // like jira, a search only embeds the first 20 worklogs
+                        if with_worklogs {
+                            json["fields"]["worklog"] = json!({
+                                "startAt": 0,
+                                "maxResults": 20,
+                                "total": issue.worklogs.len(),
+                                "worklogs": issue.worklogs.iter().take(20).collect::<Vec<_>>(),
+                            });
+                        }
+                        issues.push(json)
+                    }
+                    Ok(false) => {}
+                    Err(message) => return error(request, 400, &message),
+                }
+            }
+            let start_at = body["startAt"].as_u64().unwrap_or(0) as usize;
+            let mut max_results = body["maxResults"].as_u64().unwrap_or(50) as usize;
+            if let Some(limit) = store.page_limit {
+                max_results = max_results.min(limit);
+            }
+            let total = issues.len();
+            let page: Vec<Value> = issues.into_iter().skip(start_at).take(max_results).collect();
+            let result = json!({
+                "startAt": start_at,
+                "maxResults": max_results,
+                "total": total,
+                "issues": page,
+            });
+            respond(request, 200, &result)
+        }
+        (Method::Post, ["rest", "api", "2", "issue"]) => {
+            match create_issue(&mut store, base, &body["fields"]) {
+                Ok(created) => respond(request, 201, &created),
+                Err(errors) => respond(request, 400, &errors),
+            }
+        }
+        (Method::Post, ["rest", "api", "2", "issue", "bulk"]) => {
+            let mut issues = Vec::new();
+            let mut errors = Vec::new();
+            let updates = body["issueUpdates"].as_array().cloned().unwrap_or_default();
+            for (index, update) in updates.iter().enumerate() {
+                match create_issue(&mut store, base, &update["fields"]) {
+                    Ok(created) => issues.push(created),
+                    Err(element_errors) => errors.push(json!({
+                        "status": 400,
+                        "elementErrors": element_errors,
+                        "failedElementNumber": index,
+                    })),
+                }
+            }
+            let status = if errors.is_empty() { 201 } else { 400 };
+            respond(request, status, &json!({ "issues": issues, "errors": errors }))
+        }
+        (Method::Get, ["rest", "api", "2", "issue", "createmeta"]) => {
+            let keys = query_param(&url, "projectKeys").unwrap_or_default();
+            let projects: Vec<Value> = keys.split(',')
+                .filter(|key| store.projects.iter().any(|project| project == key))
+                .map(project_meta)
+                .collect();
+            respond(request, 200, &json!({ "projects": projects }))
+        }
+        (Method::Get, ["rest", "api", "2", "issue", key]) => match store.issue(key) {
+            Some(issue) => respond(request, 200, &store.issue_json(base, issue)),
+            None => error(request, 404, "Issue Does Not Exist"),
+        },
+        (Method::Get, ["rest", "api", "2", "issue", key, "transitions"]) => {
+            if store.issue(key).is_none() {
+                return error(request, 404, "Issue Does Not Exist");
+            }
+            let transitions: Vec<Value> = TRANSITIONS
+                .iter()
+                .map(|&(id, name, to)| json!({ "id": id, "name": name, "to": { "name": to } }))
+                .collect();
+            respond(request, 200, &json!({ "transitions": transitions }))
+        }
+        (Method::Post, ["rest", "api", "2", "issue", key, "transitions"]) => {
+            let id = body["transition"]["id"].as_str().unwrap_or("").to_string();
+            let to = match TRANSITIONS.iter().find(|&&(tid, _, _)| tid == id) {
+                Some(&(_, _, to)) => to,
+                None => return error(request, 400, "It seems that you have tried to perform a workflow operation that is not valid"),
+            };
+            match store.issue_mut(key) {
+                Some(issue) => {
+                    issue.status = to.to_string();
+                    respond(request, 204, &Value::Null)
+                }
+                None => error(request, 404, "Issue Does Not Exist"),
+            }
+        }
+        (Method::Put, ["rest", "api", "2", "issue", key, "assignee"]) => {
+            let name = body["name"].as_str().unwrap_or("").to_string();
+            if !store.users.contains_key(&name) {
+                let message = format!("User '{}' does not exist.", name);
+                return respond(
+                    request,
+                    400,
+                    &json!({ "errorMessages": [], "errors": { "assignee": message } }),
+                );
+            }
+            match store.issue_mut(key) {
+                Some(issue) => {
+                    issue.assignee = Some(name);
+                    respond(request, 204, &Value::Null)
+                }
+                None => error(request, 404, "Issue Does Not Exist"),
+            }
+        }
+        // only label updates, as in {"update": {"labels": [{"add": "x"}]}}
+        (Method::Put, ["rest", "api", "2", "issue", key]) => match store.issue_mut(key) {
+            Some(issue) => {
+                let updates = body["update"]["labels"].as_array().cloned().unwrap_or_default();
+                for update in updates {
+                    if let Some(label) = update["add"].as_str() {
+                        if !issue.labels.iter().any(|l| l == label) {
+                            issue.labels.push(label.to_string());
+                        }
+                    }
+                    if let Some(label) = update["remove"].as_str() {
+                        issue.labels.retain(|l| l != label);
+                    }
+                }
+                respond(request, 204, &Value::Null)
+            }
+            None => error(request, 404, "Issue Does Not Exist"),
+        },
+        (Method::Get, ["rest", "api", "2", "issue", key, "comment"]) => match store.issue(key) {
+            Some(issue) => {
+                let comments = json!({
+                    "startAt": 0,
+                    "maxResults": issue.comments.len(),
+                    "total": issue.comments.len(),
+                    "comments": issue.comments,
+                });
+                respond(request, 200, &comments)
+            }
+            None => error(request, 404, "Issue Does Not Exist"),
+        },
+        (Method::Post, ["rest", "api", "2", "issue", key, "comment"]) => {
+            let author = store.user_json(base, USERNAME);
+            match store.issue_mut(key) {
+                Some(issue) => {
+                    let comment = json!({
+                        "id": (issue.comments.len() + 1).to_string(),
+                        "author": author,
+                        "body": body["body"],
+                        "created": "2017-12-07T09:00:00.000+0000",
+                    });
+                    issue.comments.push(comment.clone());
+                    respond(request, 201, &comment)
+                }
+                None => error(request, 404, "Issue Does Not Exist"),
+            }
+        }
+        (Method::Get, ["rest", "api", "2", "issue", key, "worklog"]) => match store.issue(key) {
+            Some(issue) => {
+                let worklogs = json!({
+                    "startAt": 0,
+                    "maxResults": issue.worklogs.len(),
+                    "total": issue.worklogs.len(),
+                    "worklogs": issue.worklogs,
+                });
+                respond(request, 200, &worklogs)
+            }
+            None => error(request, 404, "Issue Does Not Exist"),
+        },
+        (Method::Post, ["rest", "api", "2", "issue", key, "worklog"]) => {
+            let author = store.user_json(base, USERNAME);
+            match store.issue_mut(key) {
+                Some(issue) => {
+                    let worklog = json!({
+                        "id": (issue.worklogs.len() + 1).to_string(),
+                        "author": author,
+                        "comment": body["comment"],
+                        "started": body["started"],
+                        "timeSpentSeconds": body["timeSpentSeconds"],
+                    });
+                    issue.worklogs.push(worklog.clone());
+                    respond(request, 201, &worklog)
+                }
+                None => error(request, 404, "Issue Does Not Exist"),
+            }
+        }
+        (Method::Get, ["rest", "api", "2", "issue", key, "remotelink"]) => match store.issue(key) {
+            Some(issue) => respond(request, 200, &Value::Array(issue.remote_links.clone())),
+            None => error(request, 404, "Issue Does Not Exist"),
+        },
+        (Method::Post, ["rest", "api", "2", "issue", key, "remotelink"]) => {
+            match store.issue_mut(key) {
+                Some(issue) => {
+                    let id = 10000 + issue.remote_links.len();
+                    issue.remote_links.push(json!({ "id": id, "object": body["object"] }));
+                    respond(request, 201, &json!({ "id": id }))
+                }
+                None => error(request, 404, "Issue Does Not Exist"),
+            }
+        }
+        (Method::Post, ["rest", "api", "2", "issueLink"]) => {
+            let link_type = body["type"]["name"].as_str().unwrap_or("").to_string();
+            if !LINK_TYPES.iter().any(|&(name, _, _)| name == link_type) {
+                let message = format!("No issue link type with name '{}' found.", link_type);
+                return error(request, 404, &message);
+            }
+            let inward = body["inwardIssue"]["key"].as_str().unwrap_or("").to_string();
+            let outward = body["outwardIssue"]["key"].as_str().unwrap_or("").to_string();
+            for key in &[&inward, &outward] {
+                if store.issue_mut(key).is_none() {
+                    return error(request, 404, "Issue Does Not Exist");
+                }
+            }
+            store.links.push(StoredLink {
+                link_type: link_type,
+                inward: inward,
+                outward: outward,
+            });
+            respond(request, 201, &Value::Null)
+        }
+        (Method::Post, ["rest", "api", "2", "issue", key, "attachments"]) => {
+            let checked = request.headers().iter().any(|header| {
+                header.field.equiv("X-Atlassian-Token") && header.value.as_str() == "no-check"
+            });
+            if !checked {
+                return error(request, 403, "XSRF check failed");
+            }
+            let boundary = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Content-Type"))
+                .and_then(|header| header.value.as_str().split("boundary=").nth(1))
+                .map(|boundary| boundary.to_string())
+                .unwrap_or_default();
+            let files = multipart_files(&raw, &boundary);
+            if files.is_empty() {
+                return error(request, 400, "No files were attached");
+            }
+
+            let next_id = 10200 + store
+                .issues
+                .iter()
+                .map(|issue| issue.attachments.len())
+                .sum::<usize>() as u32;
+            let attached: Vec<StoredAttachment> = files
+                .into_iter()
+                .enumerate()
+                .map(|(i, (filename, contents))| StoredAttachment {
+                    id: next_id + i as u32,
+                    filename: filename,
+                    contents: contents,
+                })
+                .collect();
+            let json: Vec<Value> = attached
+                .iter()
+                .map(|attachment| store.attachment_json(base, attachment))
+                .collect();
+            match store.issue_mut(key) {
+                Some(issue) => {
+                    issue.attachments.extend(attached);
+                    respond(request, 200, &Value::Array(json))
+                }
+                None => error(request, 404, "Issue Does Not Exist"),
+            }
+        }
+        (Method::Get, ["secure", "attachment", id, _]) => {
+            let found = store
+                .issues
+                .iter()
+                .flat_map(|issue| issue.attachments.iter())
+                .find(|attachment| attachment.id.to_string() == *id)
+                .map(|attachment| attachment.contents.clone());
+            match found {
+                Some(contents) => {
+                    let _ = request.respond(Response::from_data(contents));
+                }
+                None => error(request, 404, "Attachment not found"),
+            }
+        }
+        (Method::Get, ["rest", "api", "2", "project", key]) => {
+            if store.projects.iter().any(|p| p == *key) {
+                respond(request, 200, &json!({ "key": key }))
+            } else {
+                error(request, 404, "No project could be found")
+            }
+        }
+        (Method::Get, ["rest", "api", "2", "user"]) => {
+            let name = url.split("username=").nth(1).unwrap_or("").to_string();
+            if store.users.contains_key(&name) {
+                let user = store.user_json(base, &name);
+                respond(request, 200, &user)
+            } else {
+                error(request, 404, "The user does not exist")
+            }
+        }
+        _ => error(request, 404, "null for uri"),
+    }
+}
+
+// only decodes the commas ob's lists are joined with
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let query = url.splitn(2, '?').nth(1)?;
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key == name => Some(value.replace("%2C", ",")),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+// the same checks jira makes, as far as ob's tests need them, with the
+// created issue or jira's errors as the response body
+fn create_issue(store: &mut Store, base: &str, fields: &Value) -> Result<Value, Value> {
+    let field_error = |field: &str, message: &str| {
+        json!({ "errorMessages": [], "errors": { field: message } })
+    };
+
+    let project = fields["project"]["key"].as_str().unwrap_or("").to_string();
+    if !store.projects.contains(&project) {
+        return Err(field_error("project", "valid project is required"));
+    }
+    let assignee = fields["assignee"]["name"].as_str().map(|s| s.to_string());
+    if let Some(ref name) = assignee {
+        if !store.users.contains_key(name) {
+            let message = format!("User '{}' does not exist.", name);
+            return Err(field_error("assignee", &message));
+        }
+    }
+
+    let parent = fields["parent"]["key"].as_str().map(|s| s.to_string());
+    if let Some(ref parent) = parent {
+        if store.issue(parent).is_none() {
+            let message = format!("Could not find issue by id or key '{}'.", parent);
+            return Err(field_error("parent", &message));
+        }
+    }
+
+    let key = store.next_key(&project);
+    let mut issue = StoredIssue::new(
+        &key,
+        fields["summary"].as_str().unwrap_or(""),
+        "Open",
+    ).reporter(USERNAME);
+    issue.description = fields["description"].as_str().unwrap_or("").to_string();
+    issue.issuetype = fields["issuetype"]["name"]
+        .as_str()
+        .unwrap_or("Bug")
+        .to_string();
+    issue.assignee = assignee;
+    issue.parent = parent;
+    issue.epic = fields[EPIC_LINK_FIELD].as_str().map(|s| s.to_string());
+    issue.labels = fields["labels"]
+        .as_array()
+        .map(|labels| {
+            labels
+                .iter()
+                .filter_map(|l| l.as_str().map(|l| l.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(fields) = fields.as_object() {
+        issue.custom_fields = fields
+            .iter()
+            .filter(|&(name, _)| name.starts_with("customfield_") && name != EPIC_LINK_FIELD)
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+    }
+    store.add(issue);
+
+    let id = store.issue(&key).unwrap().id;
+    Ok(json!({
+        "id": id.to_string(),
+        "key": key,
+        "self": format!("{}rest/api/2/issue/{}", base, id),
+    }))
+}
+
+/// The custom field for a team, which Tasks have to set.
+pub const TEAM_FIELD: &str = "customfield_10200";
+
+fn field_meta(name: &str, required: bool, schema: Value) -> Value {
+    json!({ "name": name, "required": required, "hasDefaultValue": false, "schema": schema })
+}
+
+// every project has the same issue types: bugs, tasks that belong to a team
+// and sub-tasks
+fn project_meta(key: &str) -> Value {
+    let mut common = json!({
+        "summary": field_meta("Summary", true, json!({ "type": "string" })),
+        "issuetype": field_meta("Issue Type", true, json!({ "type": "issuetype" })),
+        "project": field_meta("Project", true, json!({ "type": "project" })),
+        "description": field_meta("Description", false, json!({ "type": "string" })),
+        "assignee": field_meta("Assignee", false, json!({ "type": "user" })),
+        "labels": field_meta("Labels", false, json!({ "type": "array", "items": "string" })),
+    });
+    common[EPIC_LINK_FIELD] = field_meta("Epic Link", false, json!({ "type": "any" }));
+
+    let mut task = common.clone();
+    task[TEAM_FIELD] = field_meta("Team", true, json!({ "type": "option" }));
+    task[TEAM_FIELD]["allowedValues"] = json!([{ "value": "Platform" }, { "value": "Security" }]);
+
+    let mut subtask = common.clone();
+    subtask["parent"] = field_meta("Parent", true, json!({ "type": "issuelink" }));
+
+    json!({
+        "key": key,
+        "issuetypes": [
+            { "name": "Bug", "fields": common },
+            { "name": "Task", "fields": task },
+            { "name": "Sub-task", "subtask": true, "fields": subtask },
+        ],
+    })
+}
+
+// pulls (file name, contents) out of a multipart/form-data body
+fn multipart_files(raw: &[u8], boundary: &str) -> Vec<(String, Vec<u8>)> {
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    // the first delimiter isn't preceded by a line break
+    let mut body = b"\r\n".to_vec();
+    body.extend_from_slice(raw);
+
+    let mut files = Vec::new();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while let Some(pos) = find(&body[start..], &delimiter) {
+        parts.push(&body[start..start + pos]);
+        start += pos + delimiter.len();
+    }
+    for part in parts {
+        let header_end = match find(part, b"\r\n\r\n") {
+            Some(pos) => pos,
+            None => continue,
+        };
+        let headers = String::from_utf8_lossy(&part[..header_end]);
+        let filename = headers
+            .split("filename=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next());
+        if let Some(filename) = filename {
+            files.push((filename.to_string(), part[header_end + 4..].to_vec()));
+        }
+    }
+    files
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Eq,
+    NotEq,
+    Cmp(&'static str),
+}
+
+fn tokenize(jql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = jql.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Eq);
+            }
+            '>' | '<' => {
+                chars.next();
+                let or_equal = chars.peek() == Some(&'=');
+                if or_equal {
+                    chars.next();
+                }
+                tokens.push(Token::Cmp(match (c, or_equal) {
+                    ('>', true) => ">=",
+                    ('>', false) => ">",
+                    ('<', true) => "<=",
+                    _ => "<",
+                }));
+            }
+            '!' => {
+                chars.next();
+                match chars.next() {
+                    Some('=') => tokens.push(Token::NotEq),
+                    _ => return Err("Error in the JQL Query: expected '='".to_string()),
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some('r') => value.push('\r'),
+                            Some(other) => value.push(other),
+                            None => return Err("Error in the JQL Query: unterminated string".to_string()),
+                        },
+                        Some('"') => break,
+                        Some(other) => value.push(other),
+                        None => return Err("Error in the JQL Query: unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "(),=!<>\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                // the only function we know about
+                if word.eq_ignore_ascii_case("currentuser") && chars.peek() == Some(&'(') {
+                    chars.next();
+                    if chars.next() != Some(')') {
+                        return Err("Error in the JQL Query: expected ')'".to_string());
+                    }
+                    tokens.push(Token::Str(USERNAME.to_string()));
+                    continue;
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// a value from the query, `None` standing for EMPTY
+fn value(token: &Token) -> Result<Option<String>, String> {
+    match *token {
+        Token::Str(ref s) => Ok(Some(s.clone())),
+        Token::Word(ref w) if w.eq_ignore_ascii_case("empty") || w.eq_ignore_ascii_case("null") => {
+            Ok(None)
+        }
+        Token::Word(ref w) => Ok(Some(w.clone())),
+        ref other => Err(format!("Error in the JQL Query: unexpected {:?}", other)),
+    }
+}
+
+fn field_values(field: &str, issue: &StoredIssue) -> Result<Vec<Option<String>>, String> {
+    Ok(match field.to_lowercase().as_str() {
+        "key" | "issuekey" => vec![Some(issue.key.clone())],
+        "project" => vec![Some(issue.project.clone())],
+        "status" => vec![Some(issue.status.clone())],
+        "assignee" => vec![issue.assignee.clone()],
+        "reporter" => vec![Some(issue.reporter.clone())],
+        "labels" if issue.labels.is_empty() => vec![None],
+        "labels" => issue.labels.iter().map(|l| Some(l.clone())).collect(),
+        "worklogauthor" => issue
+            .worklogs
+            .iter()
+            .map(|w| w["author"]["name"].as_str().map(|name| name.to_string()))
+            .collect(),
+        _ => return Err(format!("Field '{}' does not exist or you do not have permission to view it.", field)),
+    })
+}
+
+// the dates a field can be compared by, as seconds since the epoch
+fn field_dates(field: &str, issue: &StoredIssue) -> Result<Vec<u64>, String> {
+    Ok(match field.to_lowercase().as_str() {
+        "updated" => vec![issue.updated],
+        "worklogdate" => issue
+            .worklogs
+            .iter()
+            .filter_map(|w| w["started"].as_str())
+            .filter_map(|started| DateTime::parse_from_str(started, "%Y-%m-%dT%H:%M:%S%.3f%z").ok())
+            .map(|started| started.timestamp() as u64)
+            .collect(),
+        _ => return Err(format!("Error in the JQL Query: can't compare '{}'", field)),
+    })
+}
+
+fn same(a: &Option<String>, b: &Option<String>) -> bool {
+    match (a.as_ref(), b.as_ref()) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// relative dates like "-15m", "-2h", "-1d" or "-1w", as seconds since the epoch
+fn relative_date(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Error in the JQL Query: unsupported date '{}'", value);
+    if !value.starts_with('-') || value.len() < 3 {
+        return Err(invalid());
+    }
+    let (amount, unit) = value[1..].split_at(value.len() - 2);
+    let amount: u64 = try!(amount.parse().map_err(|_| invalid()));
+    let seconds = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(now().saturating_sub(amount * seconds))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    issue: &'a StoredIssue,
+}
+
+fn is_word(token: Option<&Token>, word: &str) -> bool {
+    match token {
+        Some(&Token::Word(ref w)) => w.eq_ignore_ascii_case(word),
+        _ => false,
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    // expr := term (AND term)*
+    fn expr(&mut self) -> Result<bool, String> {
+        let mut matched = try!(self.term());
+        while is_word(self.peek(), "and") {
+            self.pos += 1;
+            let next = try!(self.term());
+            matched = matched && next;
+        }
+        Ok(matched)
+    }
+
+    // term := NOT term | '(' expr ')' | clause
+    fn term(&mut self) -> Result<bool, String> {
+        if is_word(self.peek(), "not") {
+            self.pos += 1;
+            return Ok(!try!(self.term()));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let matched = try!(self.expr());
+            if self.peek() != Some(&Token::Close) {
+                return Err("Error in the JQL Query: expected ')'".to_string());
+            }
+            self.pos += 1;
+            return Ok(matched);
+        }
+        self.clause()
+    }
+
+    // one of `field = value`, `field != value`, `field in (...)`,
+    // `field not in (...)` or `updated >= "-5m"` (and the other comparisons)
+    fn clause(&mut self) -> Result<bool, String> {
+        let field = match self.peek() {
+            Some(&Token::Word(ref field)) => field.clone(),
+            other => return Err(format!("Error in the JQL Query: unexpected {:?}", other)),
+        };
+        self.pos += 1;
+
+        if let Some(&Token::Cmp(op)) = self.peek() {
+            self.pos += 1;
+            let threshold = match self.peek() {
+                Some(token) => match try!(value(token)) {
+                    Some(date) => try!(relative_date(&date)),
+                    None => return Err("Error in the JQL Query: expected a date".to_string()),
+                },
+                None => return Err("Error in the JQL Query: expected a date".to_string()),
+            };
+            self.pos += 1;
+            let dates = try!(field_dates(&field, self.issue));
+            return Ok(dates.iter().any(|&date| match op {
+                ">=" => date >= threshold,
+                ">" => date > threshold,
+                "<=" => date <= threshold,
+                _ => date < threshold,
+            }));
+        }
+
+        let (negate, is_list) = match self.peek() {
+            Some(&Token::Eq) => (false, false),
+            Some(&Token::NotEq) => (true, false),
+            token if is_word(token, "in") => (false, true),
+            token if is_word(token, "not") => {
+                self.pos += 1;
+                if !is_word(self.peek(), "in") {
+                    return Err("Error in the JQL Query: expected 'in'".to_string());
+                }
+                (true, true)
+            }
+            _ => return Err("Error in the JQL Query: expected an operator".to_string()),
+        };
+        self.pos += 1;
+
+        let mut expected = Vec::new();
+        if is_list {
+            if self.peek() != Some(&Token::Open) {
+                return Err("Error in the JQL Query: expected '('".to_string());
+            }
+            self.pos += 1;
+            loop {
+                match self.peek() {
+                    Some(&Token::Close) => break,
+                    Some(&Token::Comma) => self.pos += 1,
+                    Some(token) => {
+                        expected.push(try!(value(token)));
+                        self.pos += 1;
+                    }
+                    None => return Err("Error in the JQL Query: expected ')'".to_string()),
+                }
+            }
+            self.pos += 1;
+        } else {
+            match self.peek() {
+                Some(token) => expected.push(try!(value(token))),
+                None => return Err("Error in the JQL Query: expected a value".to_string()),
+            }
+            self.pos += 1;
+        }
+
+        let actual = try!(field_values(&field, self.issue));
+        let found = actual
+            .iter()
+            .any(|a| expected.iter().any(|e| same(a, e)));
+        Ok(found != negate)
+    }
+}
+
+// jira rejects a query outright when a `key = X` or `key in (...)` clause
+// names an issue that doesn't exist
+fn missing_key(jql: &str, store: &Store) -> Option<String> {
+    let tokens = tokenize(jql).ok()?;
+    let mut values = Vec::new();
+    let mut pos = 0;
+    while pos < tokens.len() {
+        let is_key = is_word(tokens.get(pos), "key") || is_word(tokens.get(pos), "issuekey");
+        pos += 1;
+        if !is_key {
+            continue;
+        }
+        // skip `=`, `!=`, `in` or `not in`
+        while tokens.get(pos) == Some(&Token::Eq)
+            || tokens.get(pos) == Some(&Token::NotEq)
+            || is_word(tokens.get(pos), "in")
+            || is_word(tokens.get(pos), "not")
+        {
+            pos += 1;
+        }
+        if tokens.get(pos) == Some(&Token::Open) {
+            pos += 1;
+            while pos < tokens.len() && tokens[pos] != Token::Close {
+                if tokens[pos] != Token::Comma {
+                    values.push(&tokens[pos]);
+                }
+                pos += 1;
+            }
+        } else if let Some(token) = tokens.get(pos) {
+            values.push(token);
+        }
+    }
+    values
+        .into_iter()
+        .filter_map(|token| value(token).ok().and_then(|key| key))
+        .find(|key| store.issue(&key.to_uppercase()).is_none())
+}
+
+// evaluates `AND`-ed clauses with optional `NOT` and parentheses, ignoring any
+// trailing `ORDER BY`
+pub fn jql_matches(jql: &str, issue: &StoredIssue) -> Result<bool, String> {
+    let tokens = try!(tokenize(jql));
+    if tokens.is_empty() {
+        return Ok(true);
+    }
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        issue: issue,
+    };
+    let matched = try!(parser.expr());
+
+    match parser.peek() {
+        None => Ok(matched),
+        token if is_word(token, "order") => Ok(matched),
+        Some(other) => Err(format!("Error in the JQL Query: unexpected {:?}", other)),
+    }
+}
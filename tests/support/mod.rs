@@ -0,0 +1,156 @@
+// not every test binary uses every helper
+#![allow(dead_code)]
+
+pub mod fake_jira;
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use self::fake_jira::{FakeJira, StoredIssue, Store};
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A fake Jira server plus a scratch directory holding a config that points
+/// `ob` at it.
+pub struct TestEnv {
+    pub jira: FakeJira,
+    pub dir: PathBuf,
+    pub config: PathBuf,
+}
+
+impl TestEnv {
+    /// An environment seeded with a handful of issues across two projects.
+    pub fn new() -> TestEnv {
+        let mut store = Store::default();
+        store.projects = vec!["FOO".to_string(), "BAR".to_string()];
+        for &(name, display) in &[
+            ("bob", "Bob"),
+            ("alice", "Alice"),
+            ("carol", "Carol"),
+            ("foo-robot", "Foo Robot"),
+        ] {
+            store.users.insert(name.to_string(), display.to_string());
+        }
+
+        store.add(
+            StoredIssue::new("FOO-1", "The build is broken", "Open")
+                .assignee("foo-robot")
+                .labels(&["interrupt"]),
+        );
+        store.add(
+            StoredIssue::new("FOO-2", "Disk full on build-03", "In Progress")
+                .assignee("bob")
+                .reporter("carol"),
+        );
+        store.add(StoredIssue::new("FOO-3", "Rotate the TLS certs", "Open"));
+        store.add(StoredIssue::new("FOO-4", "Old flaky test", "Closed").assignee("bob"));
+        store.add(StoredIssue::new("BAR-1", "Someone else's problem", "Open"));
+
+        TestEnv::with_store(store)
+    }
+
+    pub fn with_store(store: Store) -> TestEnv {
+        let jira = FakeJira::start(store);
+        let dir = env::temp_dir().join(format!(
+            "ob-test-{}-{}",
+            process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = dir.join("ob.yml");
+        let mut file = File::create(&config).unwrap();
+        write!(
+            file,
+            "config_version: 2
+config:
+  jira: \"{}\"
+  username: \"bob\"
+  auth: \"Ym9iOmh1bnRlcjI=\"
+  open_in_browser: false
+  project_keys:
+    - FOO
+  npc_users:
+    - Unassigned
+    - foo-robot
+  new_issue_defaults:
+    project_key: FOO
+    assignee: foo-robot
+    labels:
+      - interrupt
+profiles: {{}}
+",
+            jira.url
+        ).unwrap();
+
+        TestEnv {
+            jira: jira,
+            dir: dir,
+            config: config,
+        }
+    }
+
+    /// Runs `ob` against the fake server from inside the scratch directory,
+    /// with a clean environment so nothing from the host leaks in.
+    pub fn ob(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Like `ob`, with extra environment variables set.
+    pub fn ob_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        let mut command = self.command(args);
+        for &(name, value) in vars {
+            command.env(name, value);
+        }
+        command.output().unwrap()
+    }
+
+    /// Like `ob`, with `input` piped to stdin.
+    pub fn ob_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_ob"));
+        command
+            .arg("--config")
+            .arg(&self.config)
+            .args(args)
+            .current_dir(&self.dir)
+            .env_clear()
+            .env("HOME", &self.dir);
+        command
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}